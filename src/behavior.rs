@@ -0,0 +1,368 @@
+use crate::bus::{mask, Merger, Splitter, TriState, DEFAULT_BUS_WIDTH, MAX_BUS_WIDTH};
+use crate::custom::CustomGate;
+use crate::gate::GateType;
+use crate::operations::*;
+use crate::sequential::{FlipFlop, Register, REGISTER_CONTROLS};
+use crate::signal::{Level, Signal};
+use crate::simulation::TICKS_PER_SECOND;
+use std::rc::Rc;
+
+/// Half period of a new clock: one cycle per second at `TICKS_PER_SECOND`.
+pub const DEFAULT_CLOCK_PERIOD: u32 = TICKS_PER_SECOND / 2;
+
+/// Input counts a gate like AND or OR can be set to.
+pub const MIN_GATE_INPUTS: usize = 2;
+pub const MAX_GATE_INPUTS: usize = 16;
+
+/// Most unknown inputs a built-in gate tries every combination of before giving up on X.
+const MAX_UNKNOWN_INPUTS: usize = 8;
+
+/// What a gate computes. Built-in functions, components created from a circuit and stateful
+/// parts like flip-flops all implement this, so they can sit side by side in one circuit.
+pub trait GateBehavior {
+    fn inputs(&self) -> usize;
+
+    fn outputs(&self) -> usize;
+
+    /// Computes the outputs for `inputs`. Stateful behaviours may update their state here.
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool>;
+
+    /// Bits carried by each input pin.
+    fn input_widths(&self) -> Vec<u8> {
+        vec![1; self.inputs()]
+    }
+
+    /// Bits carried by each output pin.
+    fn output_widths(&self) -> Vec<u8> {
+        vec![1; self.outputs()]
+    }
+
+    /// Computes the outputs for one value per pin, bit 0 being the lowest. Behaviours without
+    /// buses only look at bit 0 of every input.
+    fn evaluate_bus(&mut self, inputs: &[u64]) -> Vec<u64> {
+        let inputs: Vec<bool> = inputs.iter().map(|x| x & 1 == 1).collect();
+        self.evaluate(&inputs).iter().map(|x| *x as u64).collect()
+    }
+
+    /// Computes the outputs in four-valued logic. Behaviours that only know 0 and 1 read
+    /// Z and X bits as 0.
+    fn evaluate_signals(&mut self, inputs: &[Signal]) -> Vec<Signal> {
+        let values: Vec<u64> = inputs.iter().map(|x| x.value).collect();
+        self.evaluate_bus(&values)
+            .into_iter()
+            .map(Signal::from)
+            .collect()
+    }
+
+    /// The bus width, for behaviours where it can be changed.
+    fn width(&self) -> Option<u8> {
+        None
+    }
+
+    fn set_width(&mut self, _bits: u8) {}
+
+    /// Changes the number of input pins, for behaviours that take any number of them.
+    fn set_inputs(&mut self, _count: usize) {}
+
+    /// The internal state, for behaviours that keep one between simulation steps.
+    fn state(&self) -> Option<Vec<bool>> {
+        None
+    }
+
+    /// Advances time by one simulation tick.
+    fn tick(&mut self) {}
+
+    /// Ticks per half period, for behaviours that change on their own.
+    fn period(&self) -> Option<u32> {
+        None
+    }
+
+    fn set_period(&mut self, _ticks: u32) {}
+
+    /// The component this gate is an instance of, for gates created from a circuit.
+    fn definition(&self) -> Option<&Rc<CustomGate>> {
+        None
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior>;
+}
+
+impl Clone for Box<dyn GateBehavior> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// A stateless gate backed by one of the functions in `operations`. Gates with inputs and
+/// outputs can work on buses, applying the function to every bit position on its own.
+#[derive(Clone, Copy)]
+pub struct Builtin {
+    inputs: usize,
+    outputs: usize,
+    bits: u8,
+    func: fn(&[bool]) -> Vec<bool>,
+}
+
+impl Builtin {
+    pub fn new(inputs: usize, outputs: usize, func: fn(&[bool]) -> Vec<bool>) -> Self {
+        Self {
+            inputs,
+            outputs,
+            bits: 1,
+            func,
+        }
+    }
+
+    fn has_width(&self) -> bool {
+        self.inputs > 0 && self.outputs > 0
+    }
+
+    /// The built-in behaviour of `gatetype` with `inputs` input pins, if it has one.
+    pub fn for_type(gatetype: GateType, inputs: usize) -> Option<Self> {
+        match gatetype {
+            GateType::Switch => Some(Self::new(0, 1, switch_lamp_func)),
+            GateType::Lamp => Some(Self::new(1, 0, switch_lamp_func)),
+            GateType::And => Some(Self::new(inputs, 1, and_func)),
+            GateType::Or => Some(Self::new(inputs, 1, or_func)),
+            GateType::Nand => Some(Self::new(inputs, 1, nand_func)),
+            GateType::XOr => Some(Self::new(inputs, 1, xor_func)),
+            GateType::Nor => Some(Self::new(inputs, 1, nor_func)),
+            GateType::XNor => Some(Self::new(inputs, 1, xnor_func)),
+            GateType::Parity => Some(Self::new(inputs, 2, parity_func)),
+            GateType::Not => Some(Self::new(1, 1, not_func)),
+            GateType::Buffer => Some(Self::new(1, 1, buffer_func)),
+            _ => None,
+        }
+    }
+}
+
+impl GateBehavior for Builtin {
+    fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool> {
+        (self.func)(inputs)
+    }
+
+    fn input_widths(&self) -> Vec<u8> {
+        vec![self.bits; self.inputs]
+    }
+
+    fn output_widths(&self) -> Vec<u8> {
+        vec![self.bits; self.outputs]
+    }
+
+    fn evaluate_bus(&mut self, inputs: &[u64]) -> Vec<u64> {
+        // a lamp has no output pins but still reports its input as its value
+        let mut outputs: Vec<u64> = Vec::new();
+        for bit in 0..self.bits {
+            let slice: Vec<bool> = inputs.iter().map(|x| x >> bit & 1 == 1).collect();
+            let values = (self.func)(&slice);
+            outputs.resize(outputs.len().max(values.len()), 0);
+            for (output, value) in outputs.iter_mut().zip(values) {
+                *output |= (value as u64) << bit;
+            }
+        }
+        outputs.iter().map(|x| x & mask(self.bits)).collect()
+    }
+
+    /// An output bit is only known if the function gives the same result whatever the
+    /// Z and X inputs are.
+    fn evaluate_signals(&mut self, inputs: &[Signal]) -> Vec<Signal> {
+        if inputs.iter().all(|x| x.is_known()) {
+            let values: Vec<u64> = inputs.iter().map(|x| x.value).collect();
+            return self
+                .evaluate_bus(&values)
+                .into_iter()
+                .map(Signal::from)
+                .collect();
+        }
+
+        let mut outputs: Vec<Signal> = Vec::new();
+        for bit in 0..self.bits {
+            let mut slice: Vec<bool> = Vec::with_capacity(inputs.len());
+            let mut unknown = Vec::new();
+            for (index, input) in inputs.iter().enumerate() {
+                match input.level(bit) {
+                    Level::High => slice.push(true),
+                    Level::Low => slice.push(false),
+                    Level::Z | Level::X => {
+                        slice.push(false);
+                        unknown.push(index);
+                    }
+                }
+            }
+
+            let results: Vec<Vec<bool>> = if unknown.len() > MAX_UNKNOWN_INPUTS {
+                Vec::new()
+            } else {
+                (0..1u32 << unknown.len())
+                    .map(|combination| {
+                        for (n, index) in unknown.iter().enumerate() {
+                            slice[*index] = combination >> n & 1 == 1;
+                        }
+                        (self.func)(&slice)
+                    })
+                    .collect()
+            };
+
+            let count = results.first().map_or(self.outputs, |x| x.len());
+            outputs.resize(outputs.len().max(count), Signal::default());
+            for (index, output) in outputs.iter_mut().enumerate().take(count) {
+                match results.first().map(|x| x[index]) {
+                    Some(value) if results.iter().all(|x| x[index] == value) => {
+                        output.value |= (value as u64) << bit;
+                    }
+                    _ => output.x |= 1 << bit,
+                }
+            }
+        }
+        outputs
+    }
+
+    fn width(&self) -> Option<u8> {
+        self.has_width().then_some(self.bits)
+    }
+
+    fn set_width(&mut self, bits: u8) {
+        if self.has_width() {
+            self.bits = bits.clamp(1, MAX_BUS_WIDTH);
+        }
+    }
+
+    fn set_inputs(&mut self, count: usize) {
+        if self.inputs >= MIN_GATE_INPUTS {
+            self.inputs = count.clamp(MIN_GATE_INPUTS, MAX_GATE_INPUTS);
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(*self)
+    }
+}
+
+/// A source whose output flips every `period` ticks.
+#[derive(Clone, Copy)]
+pub struct Clock {
+    period: u32,
+    elapsed: u32,
+    level: bool,
+}
+
+impl Clock {
+    pub fn new(period: u32) -> Self {
+        Self {
+            period: period.max(1),
+            elapsed: 0,
+            level: false,
+        }
+    }
+}
+
+impl GateBehavior for Clock {
+    fn inputs(&self) -> usize {
+        0
+    }
+
+    fn outputs(&self) -> usize {
+        1
+    }
+
+    fn evaluate(&mut self, _inputs: &[bool]) -> Vec<bool> {
+        vec![self.level]
+    }
+
+    fn state(&self) -> Option<Vec<bool>> {
+        Some(vec![self.level])
+    }
+
+    fn tick(&mut self) {
+        self.elapsed += 1;
+        if self.elapsed >= self.period {
+            self.elapsed = 0;
+            self.level = !self.level;
+        }
+    }
+
+    fn period(&self) -> Option<u32> {
+        Some(self.period)
+    }
+
+    fn set_period(&mut self, ticks: u32) {
+        self.period = ticks.max(1);
+        self.elapsed = self.elapsed.min(self.period - 1);
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(*self)
+    }
+}
+
+/// The behaviour of a freshly placed gate of `gatetype` with `inputs` input pins.
+pub fn for_type(gatetype: GateType, inputs: usize) -> Option<Box<dyn GateBehavior>> {
+    match gatetype {
+        GateType::Clock => Some(Box::new(Clock::new(DEFAULT_CLOCK_PERIOD))),
+        GateType::Register => Some(Box::new(Register::new(
+            inputs.saturating_sub(REGISTER_CONTROLS),
+        ))),
+        GateType::Splitter => Some(Box::new(Splitter::new(DEFAULT_BUS_WIDTH))),
+        GateType::Merger => Some(Box::new(Merger::new(DEFAULT_BUS_WIDTH))),
+        GateType::TriState => Some(Box::new(TriState::new(1))),
+        GateType::SrLatch
+        | GateType::DLatch
+        | GateType::DFlipFlop
+        | GateType::JkFlipFlop
+        | GateType::TFlipFlop => {
+            FlipFlop::new(gatetype).map(|x| Box::new(x) as Box<dyn GateBehavior>)
+        }
+        _ => Builtin::for_type(gatetype, inputs).map(|x| Box::new(x) as Box<dyn GateBehavior>),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(clock: &mut Clock, ticks: usize) -> Vec<bool> {
+        (0..ticks)
+            .map(|_| {
+                clock.tick();
+                clock.evaluate(&[])[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn clock_flips_every_period_ticks() {
+        let mut clock = Clock::new(2);
+        assert_eq!(clock.evaluate(&[]), vec![false]);
+        assert_eq!(
+            levels(&mut clock, 6),
+            vec![false, true, true, false, false, true]
+        );
+        assert_eq!(clock.state(), Some(vec![true]));
+    }
+
+    #[test]
+    fn shorter_period_takes_effect_within_the_current_half() {
+        let mut clock = Clock::new(10);
+        levels(&mut clock, 7);
+        clock.set_period(3);
+        assert_eq!(clock.period(), Some(3));
+        assert_eq!(levels(&mut clock, 4), vec![true, true, true, false]);
+    }
+
+    #[test]
+    fn period_is_at_least_one_tick() {
+        let mut clock = Clock::new(0);
+        assert_eq!(clock.period(), Some(1));
+        assert_eq!(levels(&mut clock, 3), vec![true, false, true]);
+        clock.set_period(0);
+        assert_eq!(clock.period(), Some(1));
+    }
+}
@@ -0,0 +1,244 @@
+use crate::behavior::GateBehavior;
+use crate::signal::{Level, Signal};
+
+/// The widest bus a pin or cable can carry.
+pub const MAX_BUS_WIDTH: u8 = 64;
+
+/// Bits of a new splitter or merger.
+pub const DEFAULT_BUS_WIDTH: u8 = 8;
+
+/// The value with the lowest `width` bits set.
+pub fn mask(width: u8) -> u64 {
+    match width {
+        0 => 0,
+        width if width >= 64 => u64::MAX,
+        width => (1 << width) - 1,
+    }
+}
+
+/// Breaks a bus into single wires, bit 0 on the top output.
+#[derive(Clone, Copy)]
+pub struct Splitter {
+    bits: u8,
+}
+
+impl Splitter {
+    pub fn new(bits: u8) -> Self {
+        Self {
+            bits: bits.clamp(1, MAX_BUS_WIDTH),
+        }
+    }
+}
+
+impl GateBehavior for Splitter {
+    fn inputs(&self) -> usize {
+        1
+    }
+
+    fn outputs(&self) -> usize {
+        self.bits as usize
+    }
+
+    fn input_widths(&self) -> Vec<u8> {
+        vec![self.bits]
+    }
+
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool> {
+        let value = inputs.first().copied().unwrap_or(false) as u64;
+        self.evaluate_bus(&[value])
+            .iter()
+            .map(|x| *x == 1)
+            .collect()
+    }
+
+    fn evaluate_bus(&mut self, inputs: &[u64]) -> Vec<u64> {
+        let value = inputs.first().copied().unwrap_or(0);
+        (0..self.bits).map(|x| value >> x & 1).collect()
+    }
+
+    fn evaluate_signals(&mut self, inputs: &[Signal]) -> Vec<Signal> {
+        let input = inputs.first().copied().unwrap_or_default();
+        (0..self.bits).map(|x| input.bit(x, 0)).collect()
+    }
+
+    fn width(&self) -> Option<u8> {
+        Some(self.bits)
+    }
+
+    fn set_width(&mut self, bits: u8) {
+        self.bits = bits.clamp(1, MAX_BUS_WIDTH);
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(*self)
+    }
+}
+
+/// Gathers single wires into a bus, the top input becoming bit 0.
+#[derive(Clone, Copy)]
+pub struct Merger {
+    bits: u8,
+}
+
+impl Merger {
+    pub fn new(bits: u8) -> Self {
+        Self {
+            bits: bits.clamp(1, MAX_BUS_WIDTH),
+        }
+    }
+}
+
+impl GateBehavior for Merger {
+    fn inputs(&self) -> usize {
+        self.bits as usize
+    }
+
+    fn outputs(&self) -> usize {
+        1
+    }
+
+    fn output_widths(&self) -> Vec<u8> {
+        vec![self.bits]
+    }
+
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool> {
+        vec![inputs.first().copied().unwrap_or(false)]
+    }
+
+    fn evaluate_bus(&mut self, inputs: &[u64]) -> Vec<u64> {
+        let value = inputs
+            .iter()
+            .take(self.bits as usize)
+            .enumerate()
+            .fold(0, |value, (bit, x)| value | (x & 1) << bit);
+        vec![value]
+    }
+
+    fn evaluate_signals(&mut self, inputs: &[Signal]) -> Vec<Signal> {
+        let signal = inputs
+            .iter()
+            .take(self.bits as usize)
+            .enumerate()
+            .map(|(bit, x)| x.bit(0, bit as u8))
+            .fold(Signal::default(), |signal, x| Signal {
+                value: signal.value | x.value,
+                z: signal.z | x.z,
+                x: signal.x | x.x,
+            });
+        vec![signal]
+    }
+
+    fn width(&self) -> Option<u8> {
+        Some(self.bits)
+    }
+
+    fn set_width(&mut self, bits: u8) {
+        self.bits = bits.clamp(1, MAX_BUS_WIDTH);
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(*self)
+    }
+}
+
+/// Passes D through while EN is set and lets go of the output (Z) otherwise, so several of
+/// them can take turns driving one bus. Inputs are D and EN.
+#[derive(Clone, Copy)]
+pub struct TriState {
+    bits: u8,
+}
+
+impl TriState {
+    pub fn new(bits: u8) -> Self {
+        Self {
+            bits: bits.clamp(1, MAX_BUS_WIDTH),
+        }
+    }
+}
+
+impl GateBehavior for TriState {
+    fn inputs(&self) -> usize {
+        2
+    }
+
+    fn outputs(&self) -> usize {
+        1
+    }
+
+    fn input_widths(&self) -> Vec<u8> {
+        vec![self.bits, 1]
+    }
+
+    fn output_widths(&self) -> Vec<u8> {
+        vec![self.bits]
+    }
+
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool> {
+        vec![inputs.iter().all(|x| *x)]
+    }
+
+    fn evaluate_signals(&mut self, inputs: &[Signal]) -> Vec<Signal> {
+        let data = inputs.first().copied().unwrap_or_default();
+        let enable = inputs.get(1).copied().unwrap_or_default();
+        let output = match enable.level(0) {
+            Level::High => Signal {
+                value: data.value & mask(self.bits),
+                // an undriven input reads as unknown once it is passed on
+                z: 0,
+                x: (data.x | data.z) & mask(self.bits),
+            },
+            Level::Low => Signal::floating(self.bits),
+            Level::Z | Level::X => Signal::unknown(self.bits),
+        };
+        vec![output]
+    }
+
+    fn width(&self) -> Option<u8> {
+        Some(self.bits)
+    }
+
+    fn set_width(&mut self, bits: u8) {
+        self.bits = bits.clamp(1, MAX_BUS_WIDTH);
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitter_puts_bit_0_on_the_top_output() {
+        let mut splitter = Splitter::new(4);
+        assert_eq!(splitter.outputs(), 4);
+        assert_eq!(splitter.input_widths(), vec![4]);
+        assert_eq!(splitter.evaluate_bus(&[0b1101]), vec![1, 0, 1, 1]);
+    }
+
+    #[test]
+    fn merger_makes_the_top_input_bit_0() {
+        let mut merger = Merger::new(4);
+        assert_eq!(merger.inputs(), 4);
+        assert_eq!(merger.output_widths(), vec![4]);
+        assert_eq!(merger.evaluate_bus(&[1, 0, 1, 1]), vec![0b1101]);
+    }
+
+    #[test]
+    fn merger_undoes_splitter_at_full_width() {
+        let value = 0xdead_beef_0123_4567;
+        let bits = Splitter::new(MAX_BUS_WIDTH).evaluate_bus(&[value]);
+        assert_eq!(Merger::new(MAX_BUS_WIDTH).evaluate_bus(&bits), vec![value]);
+    }
+
+    #[test]
+    fn widths_stay_within_one_and_the_bus_limit() {
+        let mut splitter = Splitter::new(0);
+        assert_eq!(splitter.width(), Some(1));
+        splitter.set_width(200);
+        assert_eq!(splitter.width(), Some(MAX_BUS_WIDTH));
+        assert_eq!(Merger::new(100).width(), Some(MAX_BUS_WIDTH));
+    }
+}
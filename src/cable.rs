@@ -1,24 +1,76 @@
-use sdl2::rect::Point;
+use crate::circuit::Pin;
+use crate::gate::Position;
+use crate::signal::Signal;
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+pub type CableId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum State {
     On,
     Off,
+    /// Nothing drives the wire.
+    Floating,
+    /// Some bit is unknown, usually because outputs fight over the wire.
+    Unknown,
+}
+
+impl From<bool> for State {
+    fn from(value: bool) -> Self {
+        if value {
+            State::On
+        } else {
+            State::Off
+        }
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+impl From<Signal> for State {
+    fn from(signal: Signal) -> Self {
+        if signal.x != 0 {
+            State::Unknown
+        } else if signal.z != 0 && signal.value == 0 {
+            State::Floating
+        } else {
+            State::from(signal.value != 0)
+        }
+    }
+}
+
+/// A wire from an output pin of one gate to an input pin of another. Buses carry up to
+/// 64 bits in `signal`, which is the value of the whole net at the input pin, so wires of
+/// outputs fighting over one input all show the conflict.
+///
+/// `waypoints` are bend points the user placed, which the wire passes in order. `route` is
+/// the orthogonal path from the output to the input last computed by `routing::route`,
+/// empty until the cable has been routed.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Cable {
+    pub id: CableId,
     pub state: State,
-    pub start_point: Point,
-    pub end_point: Point,
+    pub signal: Signal,
+    pub width: u8,
+    pub start: Pin,
+    pub end: Pin,
+    pub waypoints: Vec<Position>,
+    pub route: Vec<Position>,
 }
 
 impl Cable {
-    pub fn new(state: bool, start_point: Point, end_point: Point) -> Self {
+    pub fn new(signal: Signal, width: u8, start: Pin, end: Pin) -> Self {
         Self {
-            state: if state { State::On } else { State::Off },
-            start_point,
-            end_point,
+            id: 0,
+            state: signal.into(),
+            signal,
+            width,
+            start,
+            end,
+            waypoints: Vec::new(),
+            route: Vec::new(),
         }
     }
+
+    pub fn set_signal(&mut self, signal: Signal) {
+        self.signal = signal;
+        self.state = signal.into();
+    }
 }
@@ -0,0 +1,346 @@
+use crate::cable::{Cable, CableId};
+use crate::gate::{self, Gate, GateId, GateType, Position};
+use crate::routing;
+use crate::sequential::REGISTER_CONTROLS;
+
+/// A connection point on a gate, identified by the gate id and the pin index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Pin {
+    pub gate: GateId,
+    pub index: usize,
+}
+
+impl Pin {
+    pub fn new(gate: GateId, index: usize) -> Self {
+        Self { gate, index }
+    }
+}
+
+/// The headless circuit model: every gate and cable on the canvas, with stable ids.
+#[derive(Clone, Default)]
+pub struct Circuit {
+    pub gates: Vec<Gate>,
+    pub cables: Vec<Cable>,
+    next_gate_id: GateId,
+    next_cable_id: CableId,
+}
+
+impl Circuit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_gate(&mut self, mut gate: Gate) -> GateId {
+        gate.id = self.next_gate_id;
+        self.next_gate_id += 1;
+        self.gates.push(gate);
+        self.next_gate_id - 1
+    }
+
+    /// Adds a gate keeping its id, e.g. when loading a saved circuit.
+    pub fn insert_gate(&mut self, gate: Gate) -> GateId {
+        self.next_gate_id = self.next_gate_id.max(gate.id + 1);
+        self.gates.push(gate);
+        self.next_gate_id - 1
+    }
+
+    /// Adds a cable keeping its id, e.g. when a removed cable is put back.
+    pub fn insert_cable(&mut self, cable: Cable) -> CableId {
+        let id = cable.id;
+        self.next_cable_id = self.next_cable_id.max(id + 1);
+        self.cables.push(cable);
+        id
+    }
+
+    pub fn cable(&self, id: CableId) -> Option<&Cable> {
+        self.cables.iter().find(|x| x.id == id)
+    }
+
+    pub fn cable_mut(&mut self, id: CableId) -> Option<&mut Cable> {
+        self.cables.iter_mut().find(|x| x.id == id)
+    }
+
+    pub fn remove_cable(&mut self, id: CableId) -> Option<Cable> {
+        let index = self.cables.iter().position(|x| x.id == id)?;
+        Some(self.cables.remove(index))
+    }
+
+    /// The cables attached to a gate.
+    pub fn cables_of(&self, id: GateId) -> Vec<Cable> {
+        self.cables
+            .iter()
+            .filter(|x| x.start.gate == id || x.end.gate == id)
+            .cloned()
+            .collect()
+    }
+
+    pub fn index_of(&self, id: GateId) -> Option<usize> {
+        self.gates.iter().position(|x| x.id == id)
+    }
+
+    pub fn gate(&self, id: GateId) -> Option<&Gate> {
+        self.gates.iter().find(|x| x.id == id)
+    }
+
+    pub fn gate_mut(&mut self, id: GateId) -> Option<&mut Gate> {
+        self.gates.iter_mut().find(|x| x.id == id)
+    }
+
+    /// Removes a gate together with every cable attached to it.
+    pub fn remove_gate(&mut self, id: GateId) -> Option<Gate> {
+        let index = self.index_of(id)?;
+        self.cables
+            .retain(|x| x.start.gate != id && x.end.gate != id);
+        Some(self.gates.remove(index))
+    }
+
+    pub fn move_gate(&mut self, id: GateId, position: Position) {
+        if let Some(gate) = self.gate_mut(id) {
+            gate.position = position;
+        }
+    }
+
+    /// Screen coordinates of an output pin.
+    pub fn output_position(&self, pin: Pin) -> Option<Position> {
+        self.gate(pin.gate)?
+            .output_positions()
+            .get(pin.index)
+            .copied()
+    }
+
+    /// Screen coordinates of an input pin.
+    pub fn input_position(&self, pin: Pin) -> Option<Position> {
+        self.gate(pin.gate)?
+            .input_positions()
+            .get(pin.index)
+            .copied()
+    }
+
+    /// Start and end coordinates of a cable, derived from the pins it connects.
+    pub fn cable_points(&self, cable: &Cable) -> Option<(Position, Position)> {
+        Some((
+            self.output_position(cable.start)?,
+            self.input_position(cable.end)?,
+        ))
+    }
+
+    /// The points a cable is drawn through: its route, or a straight line while it has none.
+    pub fn cable_path(&self, cable: &Cable) -> Option<Vec<Position>> {
+        if cable.route.len() >= 2 {
+            return Some(cable.route.clone());
+        }
+        let (start, end) = self.cable_points(cable)?;
+        Some(vec![start, end])
+    }
+
+    /// Routes the cables in `ids` again, e.g. after gates or waypoints moved.
+    pub fn route_cables(&mut self, ids: &[CableId]) {
+        for index in 0..self.cables.len() {
+            if ids.contains(&self.cables[index].id) {
+                let route = routing::route(self, &self.cables[index]).unwrap_or_default();
+                self.cables[index].route = route;
+            }
+        }
+    }
+
+    pub fn set_waypoints(&mut self, id: CableId, waypoints: Vec<Position>) {
+        if let Some(cable) = self.cable_mut(id) {
+            cable.waypoints = waypoints;
+        }
+    }
+
+    /// Adds a waypoint where it lengthens the cable the least and returns its index.
+    pub fn add_waypoint(&mut self, id: CableId, point: Position) -> Option<usize> {
+        let cable = self.cable(id)?;
+        let (start, end) = self.cable_points(cable)?;
+        let mut stops = vec![start];
+        stops.extend(cable.waypoints.iter().copied());
+        stops.push(end);
+
+        let distance = |a: Position, b: Position| a.x.abs_diff(b.x) + a.y.abs_diff(b.y);
+        let index = (0..stops.len() - 1)
+            .min_by_key(|&i| {
+                distance(stops[i], point) + distance(point, stops[i + 1])
+                    - distance(stops[i], stops[i + 1])
+            })
+            .unwrap_or(0);
+        self.cable_mut(id)?.waypoints.insert(index, point);
+        Some(index)
+    }
+
+    pub fn input_pins(&self) -> Vec<(Pin, Position)> {
+        let mut pins = Vec::new();
+        for gate in self.gates.iter() {
+            for (index, position) in gate.input_positions().into_iter().enumerate() {
+                pins.push((Pin::new(gate.id, index), position));
+            }
+        }
+        pins
+    }
+
+    pub fn output_pins(&self) -> Vec<(Pin, Position)> {
+        let mut pins = Vec::new();
+        for gate in self.gates.iter() {
+            for (index, position) in gate.output_positions().into_iter().enumerate() {
+                pins.push((Pin::new(gate.id, index), position));
+            }
+        }
+        pins
+    }
+
+    /// Lays a cable from an output pin to an input pin, or removes it if it already exists.
+    pub fn toggle_cable(&mut self, output: Pin, input: Pin) {
+        if let Some(existing) = self
+            .cables
+            .iter()
+            .position(|x| x.start == output && x.end == input)
+        {
+            self.cables.remove(existing);
+        } else {
+            self.connect(output, input);
+        }
+    }
+
+    /// Lays a cable from an output pin to an input pin of the same bit width. An input can
+    /// be driven by several outputs, which makes a wired bus.
+    pub fn connect(&mut self, output: Pin, input: Pin) -> Option<CableId> {
+        let start_gate = self.gate(output.gate)?;
+        let end_gate = self.gate(input.gate)?;
+        let width = *start_gate.output_widths().get(output.index)?;
+        if end_gate.input_widths().get(input.index) != Some(&width)
+            || output.gate == input.gate
+            || self
+                .cables
+                .iter()
+                .any(|x| x.start == output && x.end == input)
+        {
+            return None;
+        }
+
+        let value = start_gate.output_values()[output.index];
+        let mut cable = Cable::new(value, width, output, input);
+        cable.id = self.next_cable_id;
+        self.next_cable_id += 1;
+        self.cables.push(cable);
+        Some(self.next_cable_id - 1)
+    }
+
+    /// Changes the bus width of a gate, dropping cables whose pins are gone or no longer fit.
+    /// The gate grows or shrinks to fit its pins. The LOAD and CLK cables of a register move
+    /// along with its pins as data pins come and go.
+    pub fn set_width(&mut self, id: GateId, bits: u8) {
+        let Some(gate) = self.gate_mut(id) else {
+            return;
+        };
+        let before = gate.inputs();
+        gate.set_width(bits);
+        gate.height = gate::height_for(gate.inputs().max(gate.outputs()));
+        let after = gate.inputs();
+
+        if gate.gatetype == GateType::Register && after != before {
+            let (old_data, new_data) = (before - REGISTER_CONTROLS, after - REGISTER_CONTROLS);
+            self.cables
+                .retain(|x| x.end.gate != id || x.end.index < new_data || x.end.index >= old_data);
+            for cable in self.cables.iter_mut() {
+                if cable.end.gate == id && cable.end.index >= old_data {
+                    cable.end.index = cable.end.index - old_data + new_data;
+                }
+            }
+        }
+        self.drop_loose_cables(id);
+    }
+
+    /// Changes the input count of a gate. Cables on the pins that remain are kept.
+    pub fn set_inputs(&mut self, id: GateId, count: usize) {
+        if let Some(gate) = self.gate_mut(id) {
+            gate.set_inputs(count);
+            gate.height = gate::height_for(gate.inputs().max(gate.outputs()));
+            self.drop_loose_cables(id);
+        }
+    }
+
+    /// The cables of `gate` whose pins it does not have or that have a different width.
+    pub fn loose_cables(&self, gate: &Gate) -> Vec<Cable> {
+        let (inputs, outputs) = (gate.input_widths(), gate.output_widths());
+        self.cables
+            .iter()
+            .filter(|x| {
+                (x.start.gate == gate.id && outputs.get(x.start.index) != Some(&x.width))
+                    || (x.end.gate == gate.id && inputs.get(x.end.index) != Some(&x.width))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Whether both pins of a cable exist and have its width.
+    pub fn fits(&self, cable: &Cable) -> bool {
+        let output = self
+            .gate(cable.start.gate)
+            .and_then(|x| x.output_widths().get(cable.start.index).copied());
+        let input = self
+            .gate(cable.end.gate)
+            .and_then(|x| x.input_widths().get(cable.end.index).copied());
+        output == Some(cable.width) && input == Some(cable.width)
+    }
+
+    fn drop_loose_cables(&mut self, id: GateId) {
+        let Some(gate) = self.gate(id) else {
+            return;
+        };
+        let loose: Vec<CableId> = self.loose_cables(gate).iter().map(|x| x.id).collect();
+        self.cables.retain(|x| !loose.contains(&x.id));
+    }
+
+    /// The given gates with the cables running between them, keeping their ids.
+    pub fn extract(&self, ids: &[GateId]) -> Circuit {
+        let mut fragment = Circuit::new();
+        for gate in self.gates.iter().filter(|x| ids.contains(&x.id)) {
+            fragment.insert_gate(gate.clone());
+        }
+        for cable in self.cables.iter() {
+            if ids.contains(&cable.start.gate) && ids.contains(&cable.end.gate) {
+                fragment.insert_cable(cable.clone());
+            }
+        }
+        fragment
+    }
+
+    /// Adds copies of every gate and cable of `fragment` under new ids, shifted by `offset`
+    /// with the waypoints of the cables.
+    /// Returns the ids of the new gates and cables.
+    pub fn paste(&mut self, fragment: &Circuit, offset: Position) -> (Vec<GateId>, Vec<CableId>) {
+        let mut ids = Vec::new();
+        for gate in fragment.gates.iter() {
+            let mut copy = gate.clone();
+            copy.position = Position::new(gate.position.x + offset.x, gate.position.y + offset.y);
+            ids.push((gate.id, self.add_gate(copy)));
+        }
+        let new_id = |id: GateId| ids.iter().find(|x| x.0 == id).map(|x| x.1);
+
+        let mut cables = Vec::new();
+        for cable in fragment.cables.iter() {
+            if let (Some(start), Some(end)) = (new_id(cable.start.gate), new_id(cable.end.gate)) {
+                let output = Pin::new(start, cable.start.index);
+                if let Some(id) = self.connect(output, Pin::new(end, cable.end.index)) {
+                    let shifted = cable
+                        .waypoints
+                        .iter()
+                        .map(|x| Position::new(x.x + offset.x, x.y + offset.y))
+                        .collect();
+                    self.set_waypoints(id, shifted);
+                    cables.push(id);
+                }
+            }
+        }
+        (ids.iter().map(|x| x.1).collect(), cables)
+    }
+
+    pub fn clear(&mut self) {
+        self.gates.clear();
+        self.cables.clear();
+    }
+
+    pub fn count_occurences(&self, gatetype: GateType) -> usize {
+        self.gates.iter().filter(|x| x.gatetype == gatetype).count()
+    }
+}
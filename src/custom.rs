@@ -0,0 +1,191 @@
+use crate::behavior::GateBehavior;
+use crate::circuit::Circuit;
+use crate::gate::{Gate, GateId, GateType};
+use crate::signal::Signal;
+use crate::simulation;
+use std::rc::Rc;
+
+/// A reusable component captured from a circuit: its switches become the inputs and its
+/// lamps the outputs, both in on-screen order (top to bottom, then left to right).
+#[derive(Clone)]
+pub struct CustomGate {
+    pub name: String,
+    pub circuit: Circuit,
+    pub inputs: Vec<GateId>,
+    pub outputs: Vec<GateId>,
+}
+
+impl CustomGate {
+    /// Captures `circuit` as a component called `name`. Custom gates in the circuit are
+    /// kept as instances of their definitions, so components can nest to any depth, as long
+    /// as no component ends up containing itself.
+    pub fn from_circuit(name: &str, circuit: &Circuit) -> Result<Self, String> {
+        let custom_gate = Self {
+            name: name.to_string(),
+            circuit: circuit.clone(),
+            inputs: Self::ordered(circuit, GateType::Switch),
+            outputs: Self::ordered(circuit, GateType::Lamp),
+        };
+        if custom_gate.uses(name) {
+            return Err(format!("custom gate {} cannot contain itself", name));
+        }
+        Ok(custom_gate)
+    }
+
+    /// Whether an instance of the component called `name` appears anywhere inside this one.
+    pub fn uses(&self, name: &str) -> bool {
+        self.circuit
+            .gates
+            .iter()
+            .any(|x| match x.behavior.definition() {
+                Some(definition) => definition.name == name || definition.uses(name),
+                None => false,
+            })
+    }
+
+    fn ordered(circuit: &Circuit, gatetype: GateType) -> Vec<GateId> {
+        let mut gates: Vec<&Gate> = circuit
+            .gates
+            .iter()
+            .filter(|x| x.gatetype == gatetype)
+            .collect();
+        gates.sort_by_key(|x| (x.position.y, x.position.x));
+        gates.iter().map(|x| x.id).collect()
+    }
+
+    /// Drives the captured switches of `circuit` with `inputs`, simulates it and reads the
+    /// lamps, so Z and X pass through the component like through any other gate.
+    fn evaluate(&self, circuit: &mut Circuit, inputs: &[Signal]) -> Vec<Signal> {
+        for (id, value) in self.inputs.iter().zip(inputs.iter()) {
+            if let Some(switch) = circuit.gate_mut(*id) {
+                switch.drive(*value);
+            }
+        }
+
+        simulation::simulate(circuit);
+
+        self.outputs
+            .iter()
+            .map(|id| {
+                circuit
+                    .gate(*id)
+                    .and_then(|x| x.input_values.first().copied())
+                    .unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+/// One placed instance of a `CustomGate`. Every instance simulates its own copy of the
+/// sub-circuit, so feedback loops inside it keep their state between steps.
+#[derive(Clone)]
+pub struct Composite {
+    pub definition: Rc<CustomGate>,
+    circuit: Circuit,
+}
+
+impl Composite {
+    pub fn new(definition: Rc<CustomGate>) -> Self {
+        Self {
+            circuit: definition.circuit.clone(),
+            definition,
+        }
+    }
+}
+
+impl GateBehavior for Composite {
+    fn inputs(&self) -> usize {
+        self.definition.inputs.len()
+    }
+
+    fn outputs(&self) -> usize {
+        self.definition.outputs.len()
+    }
+
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool> {
+        let inputs: Vec<Signal> = inputs.iter().map(|x| Signal::from(*x)).collect();
+        self.evaluate_signals(&inputs)
+            .iter()
+            .map(|x| x.value & 1 == 1)
+            .collect()
+    }
+
+    fn evaluate_signals(&mut self, inputs: &[Signal]) -> Vec<Signal> {
+        self.definition.evaluate(&mut self.circuit, inputs)
+    }
+
+    fn tick(&mut self) {
+        for gate in self.circuit.gates.iter_mut() {
+            gate.behavior.tick();
+        }
+    }
+
+    fn definition(&self) -> Option<&Rc<CustomGate>> {
+        Some(&self.definition)
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Pin;
+    use crate::gate::Position;
+    use crate::testing::{add, wire};
+
+    fn buffer(name: &str) -> Rc<CustomGate> {
+        let mut circuit = Circuit::new();
+        let input = add(&mut circuit, GateType::Switch, 100, 100);
+        let output = add(&mut circuit, GateType::Lamp, 300, 100);
+        wire(&mut circuit, input, 0, output, 0);
+        Rc::new(CustomGate::from_circuit(name, &circuit).expect("not recursive"))
+    }
+
+    fn containing(definition: &Rc<CustomGate>) -> Circuit {
+        let mut circuit = Circuit::new();
+        circuit.add_gate(Gate::custom(
+            definition.clone(),
+            Position::new(200, 100),
+            64,
+            64,
+        ));
+        circuit
+    }
+
+    #[test]
+    fn component_cannot_contain_itself() {
+        let inner = buffer("BUF");
+        assert!(CustomGate::from_circuit("BUF", &containing(&inner)).is_err());
+
+        let outer =
+            Rc::new(CustomGate::from_circuit("OUTER", &containing(&inner)).expect("not recursive"));
+        assert!(outer.uses("BUF"));
+        assert!(CustomGate::from_circuit("BUF", &containing(&outer)).is_err());
+        assert!(CustomGate::from_circuit("OTHER", &containing(&outer)).is_ok());
+    }
+
+    #[test]
+    fn z_and_x_pass_through_a_custom_gate() {
+        let mut circuit = Circuit::new();
+        let a = add(&mut circuit, GateType::Switch, 100, 100);
+        let b = add(&mut circuit, GateType::Switch, 100, 200);
+        let or = add(&mut circuit, GateType::Or, 250, 200);
+        let wire = add(&mut circuit, GateType::Lamp, 400, 100);
+        let sum = add(&mut circuit, GateType::Lamp, 400, 200);
+        circuit.connect(Pin::new(a, 0), Pin::new(wire, 0));
+        circuit.connect(Pin::new(a, 0), Pin::new(or, 0));
+        circuit.connect(Pin::new(b, 0), Pin::new(or, 1));
+        circuit.connect(Pin::new(or, 0), Pin::new(sum, 0));
+        let definition = CustomGate::from_circuit("PASS", &circuit).expect("not recursive");
+        let mut composite = Composite::new(Rc::new(definition));
+
+        let outputs = composite.evaluate_signals(&[Signal::floating(1), Signal::from(false)]);
+        assert_eq!(outputs, [Signal::floating(1), Signal::unknown(1)]);
+        let outputs = composite.evaluate_signals(&[Signal::floating(1), Signal::from(true)]);
+        assert_eq!(outputs, [Signal::floating(1), Signal::from(true)]);
+        assert_eq!(composite.evaluate(&[true, false]), [true, true]);
+    }
+}
@@ -1,5 +1,9 @@
-use crate::cable::{Cable, State};
-use crate::gate::Gate;
+use game::cable::{CableId, State};
+use game::circuit::{Circuit, Pin};
+use game::gate::{Gate, GateType, Position};
+use game::routing;
+use game::selection::Selection;
+use game::truth_table::TruthTable;
 use sdl2::gfx::primitives::DrawRenderer;
 use sdl2::pixels::Color;
 use sdl2::rect::{Point, Rect};
@@ -12,60 +16,386 @@ pub const JET: Color = Color::RGB(43, 44, 40);
 pub const PERSIAN_GREEN: Color = Color::RGB(51, 153, 137);
 pub const MIDDLE_BLUE_GREEN: Color = Color::RGB(125, 226, 209);
 pub const SNOW: Color = Color::RGB(255, 250, 251);
+pub const FLOATING_GREY: Color = Color::RGB(110, 110, 110);
+pub const CONTENTION_RED: Color = Color::RGB(220, 40, 40);
+pub const GRID_GREY: Color = Color::RGB(58, 59, 54);
+
+const TEXT_HEIGHT: u32 = 20;
+const ROW_HEIGHT: i32 = 22;
+// side of the handle drawn on each cable waypoint, in pixels
+const WAYPOINT_SIZE: u32 = 8;
+
+/// Room a palette entry takes in its row, its sprite and the gap after it.
+pub const MENU_PITCH: u32 = 66;
+// margin between the palette entries and the edges of its background
+const MENU_MARGIN: u32 = 5;
+
+/// How many palette entries fit in a row across a window `width` pixels wide.
+pub fn menu_columns(width: u32) -> usize {
+    (width.saturating_sub(2 * MENU_MARGIN) / MENU_PITCH).max(1) as usize
+}
+
+/// Height of the palette along the bottom of the window when it holds `items` entries.
+pub fn menu_height(items: usize, width: u32) -> u32 {
+    items.div_ceil(menu_columns(width)).max(1) as u32 * MENU_PITCH + 2 * MENU_MARGIN
+}
+
+/// Centre of the palette entry at `index` of `items`, filling rows from the top left.
+pub fn menu_position(index: usize, items: usize, width: u32, height: u32) -> Position {
+    let columns = menu_columns(width);
+    let top = height as i32 - menu_height(items, width) as i32 + MENU_MARGIN as i32;
+    let centre = |cell: usize| cell as i32 * MENU_PITCH as i32 + MENU_PITCH as i32 / 2;
+    Position::new(
+        MENU_MARGIN as i32 + centre(index % columns),
+        top + centre(index / columns),
+    )
+}
+
+pub const MIN_ZOOM: f32 = 0.25;
+pub const MAX_ZOOM: f32 = 4.0;
+// room left around the circuit by zoom to fit
+const FIT_MARGIN: i32 = 40;
+// closest the grid lines get on screen before every other one is left out
+const MIN_GRID_SPACING: f32 = 8.0;
+
+pub struct Textures<'a> {
+    pub switch: Texture<'a>,
+    pub and: Texture<'a>,
+    pub or: Texture<'a>,
+    pub not: Texture<'a>,
+    pub nand: Texture<'a>,
+    pub xor: Texture<'a>,
+    pub nor: Texture<'a>,
+    pub xnor: Texture<'a>,
+    pub buffer: Texture<'a>,
+    pub parity: Texture<'a>,
+}
+
+impl<'a> Textures<'a> {
+    pub fn for_gate(&self, gate: &Gate) -> &Texture<'a> {
+        match gate.gatetype {
+            GateType::Switch | GateType::Lamp | GateType::Clock => {
+                if gate.output_is_on().first().copied().unwrap_or(false) {
+                    &self.switch
+                } else {
+                    &self.and
+                }
+            }
+            GateType::And => &self.and,
+            GateType::Or
+            | GateType::Custom
+            | GateType::SrLatch
+            | GateType::DLatch
+            | GateType::DFlipFlop
+            | GateType::JkFlipFlop
+            | GateType::TFlipFlop
+            | GateType::Register
+            | GateType::Splitter
+            | GateType::Merger => &self.or,
+            GateType::Not => &self.not,
+            GateType::Nand => &self.nand,
+            GateType::XOr => &self.xor,
+            GateType::Nor => &self.nor,
+            GateType::XNor => &self.xnor,
+            GateType::Buffer | GateType::TriState => &self.buffer,
+            GateType::Parity => &self.parity,
+        }
+    }
+}
+
+pub fn to_point(position: Position) -> Point {
+    Point::new(position.x, position.y)
+}
+
+/// Maps circuit coordinates to window pixels. `x` and `y` are the circuit
+/// coordinates shown in the top left corner of the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    pub x: f32,
+    pub y: f32,
+    pub zoom: f32,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Camera {
+            x: 0.0,
+            y: 0.0,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl Camera {
+    pub fn to_screen(self, position: Position) -> Point {
+        Point::new(
+            ((position.x as f32 - self.x) * self.zoom).round() as i32,
+            ((position.y as f32 - self.y) * self.zoom).round() as i32,
+        )
+    }
+
+    pub fn to_world(self, x: i32, y: i32) -> Position {
+        Position::new(
+            (x as f32 / self.zoom + self.x).round() as i32,
+            (y as f32 / self.zoom + self.y).round() as i32,
+        )
+    }
+
+    /// A length in circuit coordinates as pixels, never less than one.
+    pub fn scale(&self, length: u32) -> u32 {
+        ((length as f32 * self.zoom).round() as u32).max(1)
+    }
+
+    /// Moves the view by a distance in pixels.
+    pub fn pan(&mut self, dx: i32, dy: i32) {
+        self.x -= dx as f32 / self.zoom;
+        self.y -= dy as f32 / self.zoom;
+    }
+
+    /// Multiplies the zoom by `factor`, keeping the point under the pixel `x`, `y` in place.
+    pub fn zoom_at(&mut self, x: i32, y: i32, factor: f32) {
+        let (world_x, world_y) = (x as f32 / self.zoom + self.x, y as f32 / self.zoom + self.y);
+        self.zoom = (self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        self.x = world_x - x as f32 / self.zoom;
+        self.y = world_y - y as f32 / self.zoom;
+    }
+
+    /// Zooms and pans so that every gate fits in a `width` by `height` pixel view.
+    pub fn fit(&mut self, circuit: &Circuit, width: u32, height: u32) {
+        let Some((min_x, min_y, max_x, max_y)) = circuit.gates.iter().fold(None, |bounds, gate| {
+            let (w, h) = (gate.width as i32 / 2, gate.height as i32 / 2);
+            let (x, y) = (gate.position.x, gate.position.y);
+            Some(match bounds {
+                None => (x - w, y - h, x + w, y + h),
+                Some((a, b, c, d)) => (
+                    i32::min(a, x - w),
+                    i32::min(b, y - h),
+                    i32::max(c, x + w),
+                    i32::max(d, y + h),
+                ),
+            })
+        }) else {
+            *self = Camera::default();
+            return;
+        };
+
+        let (min_x, min_y) = (min_x - FIT_MARGIN, min_y - FIT_MARGIN);
+        let (max_x, max_y) = (max_x + FIT_MARGIN, max_y + FIT_MARGIN);
+        self.zoom = f32::min(
+            width as f32 / (max_x - min_x) as f32,
+            height as f32 / (max_y - min_y) as f32,
+        )
+        .clamp(MIN_ZOOM, MAX_ZOOM);
+        self.x = (min_x + max_x) as f32 / 2.0 - width as f32 / 2.0 / self.zoom;
+        self.y = (min_y + max_y) as f32 / 2.0 - height as f32 / 2.0 / self.zoom;
+    }
+}
 
 #[allow(clippy::too_many_arguments)]
 pub fn render(
     canvas: &mut WindowCanvas,
     font: &Font,
-    positions_menu: &[Point],
-    gatenames_menu: &[&str],
-    textures_menu: &[&Texture],
-    gates: &[Gate],
-    cables: &[Cable],
-    inputs: &[Point],
-    outputs: &[Point],
+    textures: &Textures,
+    palette: &[Gate],
+    circuit: &Circuit,
+    prompt: Option<(&str, &str)>,
+    truth_table: Option<(&TruthTable, usize)>,
+    paused: bool,
+    selection: &Selection,
+    band: Option<(Position, Position)>,
+    camera: &Camera,
+    grid: Option<u32>,
 ) -> Result<(), String> {
     canvas.clear();
 
     let texture_creator = canvas.texture_creator();
-    let sprite = Rect::new(0, 0, super::SPRITE_WIDTH, super::SPRITE_HEIGHT);
+    if let Some(pitch) = grid {
+        draw_grid(canvas, camera, pitch)?;
+    }
 
-    for gate in gates.iter() {
+    for gate in circuit.gates.iter() {
         draw_sprite(
             canvas,
             font,
             &texture_creator,
-            gate.position,
+            Rect::from_center(
+                camera.to_screen(gate.position),
+                camera.scale(gate.width),
+                camera.scale(gate.height),
+            ),
             gate.gatename.to_string(),
-            gate.texture,
-            gate.sprite,
+            textures.for_gate(gate),
+            camera.scale(32),
         )?;
+
+        if let Some(state) = gate
+            .behavior
+            .state()
+            .filter(|_| gate.gatetype.is_sequential())
+        {
+            let bits: String = state.iter().map(|x| if *x { '1' } else { '0' }).collect();
+            draw_text(
+                canvas,
+                font,
+                &texture_creator,
+                &bits,
+                camera.to_screen(Position::new(
+                    gate.position.x - gate.width as i32 / 4,
+                    gate.position.y + gate.height as i32 / 2,
+                )) - Point::new(0, TEXT_HEIGHT as i32 + 2),
+                MIDDLE_BLUE_GREEN,
+            )?;
+        }
     }
 
-    for cable in cables.iter() {
-        draw_cable(canvas, cable.state, cable.start_point, cable.end_point)?;
+    for cable in circuit.cables.iter() {
+        if let Some(path) = circuit.cable_path(cable) {
+            let points: Vec<Point> = path.into_iter().map(|x| camera.to_screen(x)).collect();
+            draw_cable(
+                canvas,
+                cable.state,
+                camera.scale(if cable.width > 1 { 9 } else { 5 }),
+                &points,
+            )?;
+        }
     }
 
-    for input in inputs.iter() {
-        draw_connections(canvas, *input)?;
+    for (point, id) in routing::junctions(circuit) {
+        if let Some(cable) = circuit.cable(id) {
+            let point = camera.to_screen(point);
+            canvas.filled_circle(
+                point.x() as i16,
+                point.y() as i16,
+                camera.scale(if cable.width > 1 { 9 } else { 6 }) as i16,
+                state_color(cable.state),
+            )?;
+        }
     }
+    canvas.set_draw_color(JET);
 
-    for output in outputs.iter().rev() {
-        draw_connections(canvas, *output)?;
+    for path in circuit
+        .cables
+        .iter()
+        .filter(|x| selection.cables.contains(&x.id))
+        .filter_map(|x| circuit.cable_path(x))
+    {
+        for segment in path.windows(2) {
+            let (start_point, end_point) =
+                (camera.to_screen(segment[0]), camera.to_screen(segment[1]));
+            canvas.thick_line(
+                start_point.x() as i16,
+                start_point.y() as i16,
+                end_point.x() as i16,
+                end_point.y() as i16,
+                2,
+                SNOW,
+            )?;
+        }
+    }
+    canvas.set_draw_color(SNOW);
+    for waypoint in circuit.cables.iter().flat_map(|x| x.waypoints.iter()) {
+        canvas.draw_rect(Rect::from_center(
+            camera.to_screen(*waypoint),
+            WAYPOINT_SIZE,
+            WAYPOINT_SIZE,
+        ))?;
+    }
+    canvas.set_draw_color(JET);
+    for gate in circuit
+        .gates
+        .iter()
+        .filter(|x| selection.gates.contains(&x.id))
+    {
+        canvas.set_draw_color(SNOW);
+        canvas.draw_rect(Rect::from_center(
+            camera.to_screen(gate.position),
+            camera.scale(gate.width) + 4,
+            camera.scale(gate.height) + 4,
+        ))?;
+        canvas.set_draw_color(JET);
+    }
+
+    // bus values on top of every wire
+    for cable in circuit.cables.iter().filter(|x| x.width > 1) {
+        if let Some(path) = circuit.cable_path(cable) {
+            let middle = (path.len() - 2) / 2;
+            let (start_point, end_point) = (path[middle], path[middle + 1]);
+            draw_text(
+                canvas,
+                font,
+                &texture_creator,
+                &cable.signal.to_hex(cable.width),
+                camera.to_screen(Position::new(
+                    (start_point.x + end_point.x) / 2,
+                    (start_point.y + end_point.y) / 2,
+                )) - Point::new(0, TEXT_HEIGHT as i32),
+                SNOW,
+            )?;
+        }
+    }
+
+    let radius = camera.scale(8);
+    for (_, input) in circuit.input_pins().iter() {
+        draw_connections(canvas, camera.to_screen(*input), radius)?;
+    }
+
+    for (_, output) in circuit.output_pins().iter().rev() {
+        draw_connections(canvas, camera.to_screen(*output), radius)?;
+    }
+
+    if let Some((corner, other)) = band {
+        let (corner, other) = (camera.to_screen(corner), camera.to_screen(other));
+        canvas.set_draw_color(SNOW);
+        canvas.draw_rect(Rect::new(
+            corner.x().min(other.x()),
+            corner.y().min(other.y()),
+            corner.x().abs_diff(other.x()),
+            corner.y().abs_diff(other.y()),
+        ))?;
+        canvas.set_draw_color(JET);
     }
 
     draw_create_button(canvas, font, &texture_creator)?;
-    draw_menu_background(canvas)?;
+    if paused {
+        draw_text(
+            canvas,
+            font,
+            &texture_creator,
+            "PAUSED (N: step)",
+            Point::new(10, 10),
+            PERSIAN_GREEN,
+        )?;
+    }
+    if let Some((label, input)) = prompt {
+        draw_prompt(canvas, font, &texture_creator, label, input)?;
+    }
+    let (width, _) = canvas.output_size()?;
+    let menu_height = menu_height(palette.len(), width);
+    if let Some((truth_table, scroll)) = truth_table {
+        draw_truth_table(
+            canvas,
+            font,
+            &texture_creator,
+            truth_table,
+            scroll,
+            menu_height,
+        )?;
+    }
+    draw_menu_background(canvas, menu_height)?;
 
-    for gate in 0..positions_menu.len() {
+    for gate in palette.iter() {
         draw_sprite(
             canvas,
             font,
             &texture_creator,
-            positions_menu[gate],
-            gatenames_menu[gate].to_string(),
-            textures_menu[gate],
-            sprite,
+            Rect::from_center(
+                to_point(gate.position),
+                super::SPRITE_WIDTH,
+                super::SPRITE_HEIGHT,
+            ),
+            gate.gatename.to_string(),
+            textures.for_gate(gate),
+            32,
         )?;
     }
 
@@ -73,98 +403,127 @@ pub fn render(
     Ok(())
 }
 
+/// The index of the gate whose body holds the mouse. The gates are in circuit coordinates
+/// seen through `camera`.
 pub fn match_mouse_pos(
     mouse_pos_x: i32,
     mouse_pos_y: i32,
-    positions: &[Point],
-    width: i32,
-    height: i32,
+    gates: &[Gate],
+    camera: &Camera,
 ) -> Option<usize> {
-    for pos in positions.iter() {
-        if mouse_pos_x > pos.x() - height / 2
-            && mouse_pos_x < pos.x() + height / 2
-            && mouse_pos_y > pos.y() - width / 2
-            && mouse_pos_y < pos.y() + width / 2
-        {
-            return Some(positions.iter().position(|&x| x == *pos).unwrap());
-        }
-    }
-    None
+    let Position {
+        x: mouse_pos_x,
+        y: mouse_pos_y,
+    } = camera.to_world(mouse_pos_x, mouse_pos_y);
+    gates.iter().position(|gate| {
+        let (half_width, half_height) = (gate.width as i32 / 2, gate.height as i32 / 2);
+        mouse_pos_x > gate.position.x - half_width
+            && mouse_pos_x < gate.position.x + half_width
+            && mouse_pos_y > gate.position.y - half_height
+            && mouse_pos_y < gate.position.y + half_height
+    })
 }
 
 pub fn match_mouse_pos_con(
     mouse_pos_x: i32,
     mouse_pos_y: i32,
     is_input: bool,
-    gates: &[Gate],
+    circuit: &Circuit,
     width: i32,
     height: i32,
-) -> Option<(usize, usize, usize)> {
-    let mut con_positions: Vec<(usize, Vec<Point>)> = Vec::new();
-    let mut output_pos_index = 0;
-
-    if is_input {
-        for (index, gate) in gates.iter().enumerate() {
-            con_positions.push((index, gate.input_positions()));
-        }
+    camera: &Camera,
+) -> Option<Pin> {
+    let Position {
+        x: mouse_pos_x,
+        y: mouse_pos_y,
+    } = camera.to_world(mouse_pos_x, mouse_pos_y);
+    let con_positions = if is_input {
+        circuit.input_pins()
     } else {
-        for (index, gate) in gates.iter().enumerate() {
-            if gate.output_positions().iter().any(|&x| {
-                mouse_pos_x > x.x() - height / 2
-                    && mouse_pos_x < x.x() + height / 2
-                    && mouse_pos_y < x.y() - width / 2
-                    && mouse_pos_y < x.y() + width / 2
-            }) {
-                output_pos_index = gate
-                    .output_positions()
-                    .iter()
-                    .position(|&x| {
-                        mouse_pos_x > x.x() - height / 2
-                            && mouse_pos_x < x.x() + height / 2
-                            && mouse_pos_y < x.y() - width / 2
-                            && mouse_pos_y < x.y() + width / 2
-                    })
-                    .unwrap();
-            }
-            con_positions.push((index, gate.output_positions()));
-        }
-    }
+        circuit.output_pins()
+    };
 
-    for (index, positions) in con_positions.iter() {
-        for pos in positions.iter() {
-            if mouse_pos_x > pos.x() - height / 2
-                && mouse_pos_x < pos.x() + height / 2
-                && mouse_pos_y > pos.y() - width / 2
-                && mouse_pos_y < pos.y() + width / 2
-            {
-                return Some((
-                    *index,
-                    positions.iter().position(|&x| x == *pos).unwrap(),
-                    output_pos_index,
-                ));
-            }
-        }
-    }
+    con_positions
+        .into_iter()
+        .find(|(_, pos)| {
+            mouse_pos_x > pos.x - height / 2
+                && mouse_pos_x < pos.x + height / 2
+                && mouse_pos_y > pos.y - width / 2
+                && mouse_pos_y < pos.y + width / 2
+        })
+        .map(|(pin, _)| pin)
+}
 
-    None
+/// The cable passing within `tolerance` pixels of the mouse.
+pub fn match_mouse_pos_cable(
+    mouse_pos_x: i32,
+    mouse_pos_y: i32,
+    circuit: &Circuit,
+    tolerance: i32,
+    camera: &Camera,
+) -> Option<CableId> {
+    let mouse = camera.to_world(mouse_pos_x, mouse_pos_y);
+    let (x, y) = (mouse.x as f32, mouse.y as f32);
+    let tolerance = tolerance as f32 / camera.zoom;
+    let near = |start: Position, end: Position| {
+        let (x1, y1, x2, y2) = (start.x as f32, start.y as f32, end.x as f32, end.y as f32);
+        let length = (x2 - x1).powi(2) + (y2 - y1).powi(2);
+        let t = if length == 0.0 {
+            0.0
+        } else {
+            (((x - x1) * (x2 - x1) + (y - y1) * (y2 - y1)) / length).clamp(0.0, 1.0)
+        };
+        let (px, py) = (x1 + t * (x2 - x1), y1 + t * (y2 - y1));
+        (x - px).powi(2) + (y - py).powi(2) <= tolerance * tolerance
+    };
+    circuit
+        .cables
+        .iter()
+        .find(|cable| {
+            circuit
+                .cable_path(cable)
+                .is_some_and(|path| path.windows(2).any(|x| near(x[0], x[1])))
+        })
+        .map(|x| x.id)
 }
 
-pub fn match_create_pos(
-    canvas: &WindowCanvas,
+/// The cable and index of the waypoint under the mouse.
+pub fn match_mouse_pos_waypoint(
     mouse_pos_x: i32,
     mouse_pos_y: i32,
-    width: i32,
-    height: i32,
-) -> bool {
-    let (w_width, _) = canvas.output_size().unwrap();
-    if mouse_pos_x > w_width as i32 - width - 10
-        && mouse_pos_x < w_width as i32 - 10
-        && mouse_pos_y > 10
-        && mouse_pos_y < height + 10
-    {
-        return true;
-    }
-    false
+    circuit: &Circuit,
+    camera: &Camera,
+) -> Option<(CableId, usize)> {
+    let mouse = Point::new(mouse_pos_x, mouse_pos_y);
+    circuit.cables.iter().find_map(|cable| {
+        cable
+            .waypoints
+            .iter()
+            .position(|x| {
+                Rect::from_center(camera.to_screen(*x), WAYPOINT_SIZE + 4, WAYPOINT_SIZE + 4)
+                    .contains_point(mouse)
+            })
+            .map(|index| (cable.id, index))
+    })
+}
+
+/// The CREATE button in the top right corner of a window `width` pixels wide.
+fn create_button(width: u32) -> Rect {
+    Rect::new(width as i32 - 60, 10, 50, 30)
+}
+
+/// The panel the truth table is shown in along the right edge of the window.
+pub fn truth_table_panel(width: u32, height: u32, menu_height: u32) -> Rect {
+    Rect::new(
+        width as i32 - 420,
+        50,
+        410,
+        height.saturating_sub(menu_height + 60),
+    )
+}
+
+pub fn match_create_pos(mouse_pos_x: i32, mouse_pos_y: i32, width: u32) -> bool {
+    create_button(width).contains_point(Point::new(mouse_pos_x, mouse_pos_y))
 }
 
 fn draw_create_button(
@@ -173,7 +532,7 @@ fn draw_create_button(
     texture_creator: &TextureCreator<WindowContext>,
 ) -> Result<(), String> {
     let (width, _) = canvas.output_size()?;
-    let back_rect = Rect::new(width as i32 - 60, 10, 50, 30);
+    let back_rect = create_button(width);
     let font_rect = Rect::from_center(back_rect.center(), 40, 20);
 
     let text = "CREATE".to_string();
@@ -196,42 +555,211 @@ fn draw_create_button(
     Ok(())
 }
 
-fn draw_connections(canvas: &mut WindowCanvas, position: Point) -> Result<(), String> {
-    canvas.filled_circle(position.x() as i16, position.y() as i16, 8, EERIE_BLACK)?;
+fn draw_prompt(
+    canvas: &mut WindowCanvas,
+    font: &Font,
+    texture_creator: &TextureCreator<WindowContext>,
+    label: &str,
+    input: &str,
+) -> Result<(), String> {
+    let (width, _) = canvas.output_size()?;
+
+    let text = format!("{} {}_", label, input);
+    let (text_width, _) = font.size_of(&text).map_err(|e| e.to_string())?;
+    let back_width = (text_width * TEXT_HEIGHT / font.height().max(1) as u32 + 10).max(150);
+    let back_rect = Rect::new(width as i32 - 70 - back_width as i32, 10, back_width, 30);
+
+    canvas.set_draw_color(EERIE_BLACK);
+    canvas.draw_rect(back_rect)?;
+    canvas.fill_rect(back_rect)?;
+
+    draw_text(
+        canvas,
+        font,
+        texture_creator,
+        &text,
+        Point::new(back_rect.x() + 5, back_rect.y() + 5),
+        SNOW,
+    )?;
     canvas.set_draw_color(JET);
 
     Ok(())
 }
 
-// [TODO] draw better lines
-fn draw_cable(
+fn draw_truth_table(
     canvas: &mut WindowCanvas,
-    state: State,
-    start_point: Point,
-    end_point: Point,
+    font: &Font,
+    texture_creator: &TextureCreator<WindowContext>,
+    truth_table: &TruthTable,
+    scroll: usize,
+    menu_height: u32,
 ) -> Result<(), String> {
-    let color = if state == State::On {
-        MIDDLE_BLUE_GREEN
-    } else {
-        EERIE_BLACK
-    };
+    let (width, height) = canvas.output_size()?;
+    let back_rect = truth_table_panel(width, height, menu_height);
 
-    canvas.thick_line(
-        start_point.x() as i16,
-        start_point.y() as i16,
-        end_point.x() as i16,
-        end_point.y() as i16,
-        5,
-        color,
+    canvas.set_draw_color(EERIE_BLACK);
+    canvas.draw_rect(back_rect)?;
+    canvas.fill_rect(back_rect)?;
+
+    let header = format!(
+        "{} | {}",
+        truth_table.inputs.join(" "),
+        truth_table.outputs.join(" ")
+    );
+    draw_text(
+        canvas,
+        font,
+        texture_creator,
+        &header,
+        Point::new(back_rect.x() + 10, back_rect.y() + 5),
+        PERSIAN_GREEN,
     )?;
+
+    let visible_rows = (back_rect.height() as usize).saturating_sub(35) / ROW_HEIGHT as usize;
+    for (line, (inputs, outputs)) in truth_table
+        .rows
+        .iter()
+        .skip(scroll)
+        .take(visible_rows)
+        .enumerate()
+    {
+        let bits = |values: &[bool]| {
+            values
+                .iter()
+                .map(|x| if *x { "1" } else { "0" })
+                .collect::<Vec<&str>>()
+                .join(" ")
+        };
+        draw_text(
+            canvas,
+            font,
+            texture_creator,
+            &format!("{} | {}", bits(inputs), bits(outputs)),
+            Point::new(
+                back_rect.x() + 10,
+                back_rect.y() + 30 + (line as i32 * ROW_HEIGHT),
+            ),
+            SNOW,
+        )?;
+    }
     canvas.set_draw_color(JET);
 
     Ok(())
 }
 
-fn draw_menu_background(canvas: &mut WindowCanvas) -> Result<(), String> {
+/// Draws one line of text `TEXT_HEIGHT` pixels high with its top left corner at `position`.
+fn draw_text(
+    canvas: &mut WindowCanvas,
+    font: &Font,
+    texture_creator: &TextureCreator<WindowContext>,
+    text: &str,
+    position: Point,
+    color: Color,
+) -> Result<(), String> {
+    if text.is_empty() {
+        return Ok(());
+    }
+    let surface = font
+        .render(text)
+        .blended(color)
+        .map_err(|e| e.to_string())?;
+    let font_rect = Rect::new(
+        position.x(),
+        position.y(),
+        surface.width() * TEXT_HEIGHT / surface.height().max(1),
+        TEXT_HEIGHT,
+    );
+
+    let text = texture_creator
+        .create_texture_from_surface(&surface)
+        .map_err(|e| e.to_string())?;
+    canvas.copy(&text, None, font_rect)?;
+
+    Ok(())
+}
+
+/// Draws the grid lines visible through `camera`, thinned out when zoomed far out.
+fn draw_grid(canvas: &mut WindowCanvas, camera: &Camera, pitch: u32) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    let mut pitch = pitch.max(1) as f32;
+    while pitch * camera.zoom < MIN_GRID_SPACING {
+        pitch *= 2.0;
+    }
+
+    canvas.set_draw_color(GRID_GREY);
+    let mut x = (camera.x / pitch).ceil() * pitch;
+    while (x - camera.x) * camera.zoom < width as f32 {
+        let screen_x = ((x - camera.x) * camera.zoom).round() as i32;
+        canvas.draw_line(Point::new(screen_x, 0), Point::new(screen_x, height as i32))?;
+        x += pitch;
+    }
+    let mut y = (camera.y / pitch).ceil() * pitch;
+    while (y - camera.y) * camera.zoom < height as f32 {
+        let screen_y = ((y - camera.y) * camera.zoom).round() as i32;
+        canvas.draw_line(Point::new(0, screen_y), Point::new(width as i32, screen_y))?;
+        y += pitch;
+    }
+    canvas.set_draw_color(JET);
+
+    Ok(())
+}
+
+fn draw_connections(canvas: &mut WindowCanvas, position: Point, radius: u32) -> Result<(), String> {
+    canvas.filled_circle(
+        position.x() as i16,
+        position.y() as i16,
+        radius as i16,
+        EERIE_BLACK,
+    )?;
+    canvas.set_draw_color(JET);
+
+    Ok(())
+}
+
+fn state_color(state: State) -> Color {
+    match state {
+        State::On => MIDDLE_BLUE_GREEN,
+        State::Off => EERIE_BLACK,
+        State::Floating => FLOATING_GREY,
+        State::Unknown => CONTENTION_RED,
+    }
+}
+
+/// Draws a wire through `points`, with round joints where it bends.
+fn draw_cable(
+    canvas: &mut WindowCanvas,
+    state: State,
+    thickness: u32,
+    points: &[Point],
+) -> Result<(), String> {
+    let color = state_color(state);
+
+    for segment in points.windows(2) {
+        canvas.thick_line(
+            segment[0].x() as i16,
+            segment[0].y() as i16,
+            segment[1].x() as i16,
+            segment[1].y() as i16,
+            thickness.min(u8::MAX as u32) as u8,
+            color,
+        )?;
+    }
+    for joint in points.iter().skip(1).take(points.len().saturating_sub(2)) {
+        canvas.filled_circle(
+            joint.x() as i16,
+            joint.y() as i16,
+            (thickness / 2) as i16,
+            color,
+        )?;
+    }
+    canvas.set_draw_color(JET);
+
+    Ok(())
+}
+
+fn draw_menu_background(canvas: &mut WindowCanvas, menu_height: u32) -> Result<(), String> {
     let (width, height) = canvas.output_size()?;
-    let background_menu = Rect::new(0, height as i32 - 76, width, 76);
+    let background_menu = Rect::new(0, height as i32 - menu_height as i32, width, menu_height);
 
     canvas.set_draw_color(EERIE_BLACK);
     canvas.draw_rect(background_menu)?;
@@ -246,13 +774,12 @@ fn draw_sprite(
     canvas: &mut WindowCanvas,
     font: &Font,
     texture_creator: &TextureCreator<WindowContext>,
-    position: Point,
+    screen_rect: Rect,
     gatename: String,
     texture: &Texture,
-    sprite: Rect,
+    label_size: u32,
 ) -> Result<(), String> {
-    let screen_rect = Rect::from_center(position, sprite.width(), sprite.height());
-    let font_rect = Rect::from_center(position, 32, 32);
+    let font_rect = Rect::from_center(screen_rect.center(), label_size, label_size);
 
     let text = gatename;
     let surface = font
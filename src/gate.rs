@@ -1,10 +1,27 @@
-use sdl2::rect::{Point, Rect};
-use sdl2::render::Texture;
+use crate::behavior::GateBehavior;
+use crate::custom::{Composite, CustomGate};
+use crate::signal::Signal;
+use std::fmt;
+use std::rc::Rc;
+use std::str::FromStr;
 
 const OFFSET: i32 = 8;
 
-#[allow(dead_code)]
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// Width of a gate and height of one with up to three pins on a side.
+pub const GATE_SIZE: u32 = 64;
+
+/// Height of a gate with `connections` pins on its busier side, doubled until they fit.
+pub fn height_for(connections: usize) -> u32 {
+    let mut i = 1;
+    while connections > 2usize.pow(i) + 1 {
+        i += 1;
+    }
+    GATE_SIZE * 2u32.pow(i - 1)
+}
+
+pub type GateId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum GateType {
     Switch,
     And,
@@ -12,100 +29,237 @@ pub enum GateType {
     Not,
     Nand,
     XOr,
+    Nor,
+    XNor,
+    Buffer,
+    Parity,
     Lamp,
+    Clock,
+    SrLatch,
+    DLatch,
+    DFlipFlop,
+    JkFlipFlop,
+    TFlipFlop,
+    Register,
+    Splitter,
+    Merger,
+    TriState,
     Custom,
 }
 
-#[derive(Clone, Copy)]
-pub struct Gate<'a> {
+impl GateType {
+    /// Latches, flip-flops and registers, which keep state between simulation steps.
+    pub fn is_sequential(self) -> bool {
+        matches!(
+            self,
+            GateType::SrLatch
+                | GateType::DLatch
+                | GateType::DFlipFlop
+                | GateType::JkFlipFlop
+                | GateType::TFlipFlop
+                | GateType::Register
+        )
+    }
+}
+
+impl fmt::Display for GateType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for GateType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Switch" => Ok(GateType::Switch),
+            "And" => Ok(GateType::And),
+            "Or" => Ok(GateType::Or),
+            "Not" => Ok(GateType::Not),
+            "Nand" => Ok(GateType::Nand),
+            "XOr" => Ok(GateType::XOr),
+            "Nor" => Ok(GateType::Nor),
+            "XNor" => Ok(GateType::XNor),
+            "Buffer" => Ok(GateType::Buffer),
+            "Parity" => Ok(GateType::Parity),
+            "Lamp" => Ok(GateType::Lamp),
+            "Clock" => Ok(GateType::Clock),
+            "SrLatch" => Ok(GateType::SrLatch),
+            "DLatch" => Ok(GateType::DLatch),
+            "DFlipFlop" => Ok(GateType::DFlipFlop),
+            "JkFlipFlop" => Ok(GateType::JkFlipFlop),
+            "TFlipFlop" => Ok(GateType::TFlipFlop),
+            "Register" => Ok(GateType::Register),
+            "Splitter" => Ok(GateType::Splitter),
+            "Merger" => Ok(GateType::Merger),
+            "TriState" => Ok(GateType::TriState),
+            "Custom" => Ok(GateType::Custom),
+            _ => Err(format!("unknown gate type `{}`", s)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub fn new(x: i32, y: i32) -> Self {
+        Self { x, y }
+    }
+
+    /// The nearest point on a grid with `pitch` pixels between lines.
+    pub fn snap(self, pitch: i32) -> Self {
+        let snap = |x: i32| (x + pitch / 2).div_euclid(pitch) * pitch;
+        Self::new(snap(self.x), snap(self.y))
+    }
+}
+
+#[derive(Clone)]
+pub struct Gate {
+    pub id: GateId,
     pub gatetype: GateType,
-    pub gatename: &'a str,
-    pub position: Point,
-    pub texture: &'a Texture<'a>,
-    pub sprite: Rect,
-    pub inputs: usize,
-    pub outputs: usize,
-    pub comp_func: fn(&[bool]) -> Vec<bool>,
-    pub input_values: Option<u64>,
+    pub gatename: String,
+    pub position: Position,
+    pub width: u32,
+    pub height: u32,
+    pub behavior: Box<dyn GateBehavior>,
+    /// The value on each input pin, bit 0 being the lowest bit of a bus.
+    pub input_values: Vec<Signal>,
+    output_values: Vec<Signal>,
+    /// What a switch drives: 0 or 1 when flipped by hand, anything when it stands for an
+    /// input of a custom gate.
+    switch_value: Signal,
 }
 
-impl<'a> Gate<'a> {
-    #[allow(clippy::too_many_arguments)]
+impl Gate {
     pub fn new(
         gatetype: GateType,
-        gatename: &'a str,
-        position: Point,
-        texture: &'a Texture<'a>,
-        sprite: Rect,
-        inputs: usize,
-        outputs: usize,
-        comp_func: fn(&[bool]) -> Vec<bool>,
-        input_values: Option<u64>,
+        gatename: &str,
+        position: Position,
+        width: u32,
+        height: u32,
+        behavior: Box<dyn GateBehavior>,
     ) -> Self {
-        Self {
+        let mut gate = Self {
+            id: 0,
             gatetype,
-            gatename,
+            gatename: gatename.to_string(),
             position,
-            texture,
-            sprite,
-            inputs,
-            outputs,
-            comp_func,
-            input_values,
-        }
+            width,
+            height,
+            input_values: vec![Signal::default(); behavior.inputs()],
+            output_values: vec![Signal::default(); behavior.outputs()],
+            behavior,
+            switch_value: Signal::default(),
+        };
+        gate.evaluate();
+        gate
+    }
+
+    /// An instance of a component created from a circuit.
+    pub fn custom(definition: Rc<CustomGate>, position: Position, width: u32, height: u32) -> Self {
+        Self::new(
+            GateType::Custom,
+            &definition.name.clone(),
+            position,
+            width,
+            height,
+            Box::new(Composite::new(definition)),
+        )
     }
 
-    pub fn input_positions(&self) -> Vec<Point> {
+    pub fn inputs(&self) -> usize {
+        self.behavior.inputs()
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.behavior.outputs()
+    }
+
+    pub fn input_widths(&self) -> Vec<u8> {
+        self.behavior.input_widths()
+    }
+
+    pub fn output_widths(&self) -> Vec<u8> {
+        self.behavior.output_widths()
+    }
+
+    /// Changes the bus width of gates that support it and recomputes the outputs.
+    pub fn set_width(&mut self, bits: u8) {
+        self.behavior.set_width(bits);
+        self.evaluate();
+    }
+
+    /// Changes the input count of gates that support it and recomputes the outputs.
+    pub fn set_inputs(&mut self, count: usize) {
+        self.behavior.set_inputs(count);
+        self.evaluate();
+    }
+
+    pub fn switch_is_on(&self) -> bool {
+        self.switch_value.value & 1 == 1
+    }
+
+    /// Flips a switch to `on` and updates its output.
+    pub fn set_switch(&mut self, on: bool) {
+        self.drive(Signal::from(on));
+    }
+
+    /// Makes a switch drive `signal`, Z and X included, and updates its output.
+    pub fn drive(&mut self, signal: Signal) {
+        self.switch_value = signal;
+        self.evaluate();
+    }
+
+    pub fn input_positions(&self) -> Vec<Position> {
+        let inputs = self.inputs();
         let mut input_pos = Vec::new();
-        for i in 1..self.inputs + 1 {
-            input_pos.push(Point::new(
-                self.position.x() - self.sprite.width() as i32 / 2,
-                self.position.y() as i32 - ((self.sprite.height() as i32 + OFFSET) / 2)
-                    + i as i32
-                        * ((self.sprite.height() as i32 + OFFSET) / (self.inputs as i32 + 1)),
+        for i in 1..inputs + 1 {
+            input_pos.push(Position::new(
+                self.position.x - self.width as i32 / 2,
+                self.position.y - ((self.height as i32 + OFFSET) / 2)
+                    + i as i32 * ((self.height as i32 + OFFSET) / (inputs as i32 + 1)),
             ));
         }
         input_pos
     }
 
-    pub fn output_positions(&self) -> Vec<Point> {
+    pub fn output_positions(&self) -> Vec<Position> {
+        let outputs = self.outputs();
         let mut output_pos = Vec::new();
-        for i in 1..self.outputs + 1 {
-            output_pos.push(Point::new(
-                self.position.x() + self.sprite.width() as i32 / 2,
-                self.position.y() as i32 - ((self.sprite.height() as i32 + OFFSET) / 2)
-                    + i as i32
-                        * ((self.sprite.height() as i32 + OFFSET) / (self.outputs as i32 + 1)),
+        for i in 1..outputs + 1 {
+            output_pos.push(Position::new(
+                self.position.x + self.width as i32 / 2,
+                self.position.y - ((self.height as i32 + OFFSET) / 2)
+                    + i as i32 * ((self.height as i32 + OFFSET) / (outputs as i32 + 1)),
             ));
         }
         output_pos
     }
 
+    /// Which outputs had a known bit set after the last `evaluate`.
     pub fn output_is_on(&self) -> Vec<bool> {
-        if self.input_values.is_some() {
-            return (self.comp_func)(&Self::convert_u64_in_bools(
-                self.input_values.unwrap(),
-                self.inputs,
-            ));
-        }
-        vec![false; self.outputs]
+        self.output_values.iter().map(|x| x.value != 0).collect()
     }
 
-    fn convert_u64_in_bools(input_u64: u64, input_count: usize) -> Vec<bool> {
-        let mut input_vec = Vec::<bool>::new();
-
-        let input_bin = format!("{:b}", input_u64);
-        let mut initial_len = input_bin.len();
-
-        while initial_len < input_count {
-            input_vec.push(false);
-            initial_len += 1;
-        }
-
-        for bin in input_bin.chars() {
-            input_vec.push(bin == '1');
-        }
+    /// The output values computed by the last `evaluate`, one per pin.
+    pub fn output_values(&self) -> &[Signal] {
+        &self.output_values
+    }
 
-        input_vec
+    /// Runs the behaviour on the current input values and stores the outputs.
+    pub fn evaluate(&mut self) -> Vec<Signal> {
+        self.output_values = if self.gatetype == GateType::Switch {
+            // a switch has no input pins, it drives the value it is set to
+            vec![self.switch_value]
+        } else {
+            self.input_values.resize(self.inputs(), Signal::default());
+            self.behavior.evaluate_signals(&self.input_values)
+        };
+        self.output_values.clone()
     }
 }
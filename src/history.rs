@@ -0,0 +1,342 @@
+use crate::cable::{Cable, CableId};
+use crate::circuit::Circuit;
+use crate::custom::CustomGate;
+use crate::gate::{Gate, GateId, Position};
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+/// Edits kept for undo before the oldest ones are dropped.
+pub const MAX_HISTORY: usize = 200;
+
+/// One reversible edit of a circuit.
+#[derive(Clone)]
+pub enum Command {
+    /// A placed gate, including its id.
+    AddGate(Gate),
+    /// A deleted gate with the cables that were attached to it.
+    RemoveGate(Gate, Vec<Cable>),
+    MoveGate {
+        id: GateId,
+        from: Position,
+        to: Position,
+    },
+    Connect(Cable),
+    Disconnect(Cable),
+    /// A new input count, with the cables on the inputs that went away.
+    SetInputs {
+        id: GateId,
+        from: usize,
+        to: usize,
+        dropped: Vec<Cable>,
+    },
+    /// A new bus width, with the cables that no longer fit.
+    SetWidth {
+        id: GateId,
+        from: u8,
+        to: u8,
+        dropped: Vec<Cable>,
+    },
+    /// The bend points of a cable replaced, e.g. by adding or dragging one.
+    SetWaypoints {
+        id: CableId,
+        from: Vec<Position>,
+        to: Vec<Position>,
+    },
+    /// A switch flipped to `on`.
+    SetSwitch {
+        id: GateId,
+        on: bool,
+    },
+    /// Everything removed from the canvas, with the circuit as it was.
+    Clear(Circuit),
+    /// A new custom gate. It does not touch the circuit; the front end owns the palette.
+    CreateCustomGate(Rc<CustomGate>),
+    /// Several commands undone and redone as one, e.g. moving a selection.
+    Batch(Vec<Command>),
+}
+
+impl Command {
+    /// Changing the input count of a gate, or `None` if the count would stay the same.
+    pub fn set_inputs(circuit: &Circuit, id: GateId, count: usize) -> Option<Command> {
+        let gate = circuit.gate(id)?;
+        let mut changed = gate.clone();
+        changed.set_inputs(count);
+        (changed.inputs() != gate.inputs()).then(|| Command::SetInputs {
+            id,
+            from: gate.inputs(),
+            to: changed.inputs(),
+            dropped: circuit.loose_cables(&changed),
+        })
+    }
+
+    /// Changing the bus width of a gate, or `None` if it has none or it would stay the same.
+    pub fn set_width(circuit: &Circuit, id: GateId, bits: u8) -> Option<Command> {
+        let from = circuit.gate(id)?.behavior.width()?;
+        // a register moves some cables to other pins, so try it on a copy
+        let mut changed = circuit.clone();
+        changed.set_width(id, bits);
+        let to = changed.gate(id)?.behavior.width()?;
+        (to != from).then(|| Command::SetWidth {
+            id,
+            from,
+            to,
+            dropped: circuit
+                .cables
+                .iter()
+                .filter(|x| changed.cable(x.id).is_none())
+                .cloned()
+                .collect(),
+        })
+    }
+
+    pub fn apply(&self, circuit: &mut Circuit) {
+        match self {
+            Command::AddGate(gate) => {
+                circuit.insert_gate(gate.clone());
+            }
+            Command::RemoveGate(gate, _) => {
+                circuit.remove_gate(gate.id);
+            }
+            Command::MoveGate { id, to, .. } => circuit.move_gate(*id, *to),
+            Command::Connect(cable) => restore(circuit, cable),
+            Command::Disconnect(cable) => {
+                circuit.remove_cable(cable.id);
+            }
+            Command::SetInputs { id, to, .. } => circuit.set_inputs(*id, *to),
+            Command::SetWidth { id, to, .. } => circuit.set_width(*id, *to),
+            Command::SetWaypoints { id, to, .. } => circuit.set_waypoints(*id, to.clone()),
+            Command::SetSwitch { id, on } => {
+                if let Some(gate) = circuit.gate_mut(*id) {
+                    gate.set_switch(*on);
+                }
+            }
+            Command::Clear(_) => circuit.clear(),
+            Command::CreateCustomGate(_) => {}
+            Command::Batch(commands) => {
+                for command in commands.iter() {
+                    command.apply(circuit);
+                }
+            }
+        }
+    }
+
+    pub fn revert(&self, circuit: &mut Circuit) {
+        match self {
+            Command::AddGate(gate) => {
+                circuit.remove_gate(gate.id);
+            }
+            Command::RemoveGate(gate, cables) => {
+                circuit.insert_gate(gate.clone());
+                for cable in cables.iter() {
+                    restore(circuit, cable);
+                }
+            }
+            Command::MoveGate { id, from, .. } => circuit.move_gate(*id, *from),
+            Command::Connect(cable) => {
+                circuit.remove_cable(cable.id);
+            }
+            Command::Disconnect(cable) => restore(circuit, cable),
+            Command::SetInputs {
+                id, from, dropped, ..
+            } => {
+                circuit.set_inputs(*id, *from);
+                for cable in dropped.iter() {
+                    restore(circuit, cable);
+                }
+            }
+            Command::SetWidth {
+                id, from, dropped, ..
+            } => {
+                circuit.set_width(*id, *from);
+                for cable in dropped.iter() {
+                    restore(circuit, cable);
+                }
+            }
+            Command::SetWaypoints { id, from, .. } => circuit.set_waypoints(*id, from.clone()),
+            Command::SetSwitch { id, on } => {
+                if let Some(gate) = circuit.gate_mut(*id) {
+                    gate.set_switch(!on);
+                }
+            }
+            Command::Clear(before) => *circuit = before.clone(),
+            Command::CreateCustomGate(_) => {}
+            Command::Batch(commands) => {
+                for command in commands.iter().rev() {
+                    command.revert(circuit);
+                }
+            }
+        }
+    }
+}
+
+/// Puts a cable back unless one of its pins is gone or no longer has its width, so a stale
+/// command can never leave a cable the file format cannot hold.
+fn restore(circuit: &mut Circuit, cable: &Cable) {
+    if circuit.fits(cable) && circuit.cable(cable.id).is_none() {
+        circuit.insert_cable(cable.clone());
+    }
+}
+
+/// Undo and redo stacks of commands. Only the last `limit` commands can be undone.
+pub struct History {
+    undo: VecDeque<Command>,
+    redo: Vec<Command>,
+    limit: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(MAX_HISTORY)
+    }
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Applies `command` to `circuit` and records it.
+    pub fn execute(&mut self, command: Command, circuit: &mut Circuit) {
+        command.apply(circuit);
+        self.push(command);
+    }
+
+    /// Records a command that has already been applied, e.g. a finished drag.
+    pub fn push(&mut self, command: Command) {
+        self.redo.clear();
+        self.undo.push_back(command);
+        if self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Reverts the last command and returns it.
+    pub fn undo(&mut self, circuit: &mut Circuit) -> Option<&Command> {
+        let command = self.undo.pop_back()?;
+        command.revert(circuit);
+        self.redo.push(command);
+        self.redo.last()
+    }
+
+    /// Applies the last undone command again and returns it.
+    pub fn redo(&mut self, circuit: &mut Circuit) -> Option<&Command> {
+        let command = self.redo.pop()?;
+        command.apply(circuit);
+        self.undo.push_back(command);
+        self.undo.back()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Pin;
+    use crate::gate::GateType;
+    use crate::testing::gate;
+
+    #[test]
+    fn undo_and_redo_move_and_cable_edits() {
+        let mut circuit = Circuit::new();
+        let mut history = History::default();
+        let switch = circuit.add_gate(gate(GateType::Switch));
+        let lamp = circuit.add_gate(gate(GateType::Lamp));
+        let cable = circuit
+            .connect(Pin::new(switch, 0), Pin::new(lamp, 0))
+            .and_then(|x| circuit.cable(x))
+            .expect("cable fits")
+            .clone();
+        history.push(Command::Connect(cable.clone()));
+        let (from, to) = (Position::default(), Position::new(200, 100));
+        history.execute(Command::MoveGate { id: lamp, from, to }, &mut circuit);
+
+        history.undo(&mut circuit);
+        assert_eq!(circuit.gate(lamp).expect("lamp exists").position, from);
+        history.undo(&mut circuit);
+        assert!(circuit.cables.is_empty());
+        assert!(history.undo(&mut circuit).is_none());
+
+        history.redo(&mut circuit);
+        assert_eq!(circuit.cable(cable.id).map(|x| x.end), Some(cable.end));
+        history.redo(&mut circuit);
+        assert_eq!(circuit.gate(lamp).expect("lamp exists").position, to);
+        assert!(history.redo(&mut circuit).is_none());
+    }
+
+    #[test]
+    fn new_edit_drops_the_redo_stack() {
+        let mut circuit = Circuit::new();
+        let mut history = History::default();
+        let added = gate(GateType::And);
+        history.execute(Command::AddGate(added.clone()), &mut circuit);
+        history.undo(&mut circuit);
+        assert!(circuit.gates.is_empty());
+
+        history.execute(Command::AddGate(added), &mut circuit);
+        history.undo(&mut circuit);
+        history.redo(&mut circuit);
+        assert_eq!(circuit.gates.len(), 1);
+        assert!(history.redo(&mut circuit).is_none());
+    }
+
+    #[test]
+    fn undo_restores_cables_dropped_with_an_input() {
+        let mut circuit = Circuit::new();
+        let mut history = History::default();
+        let switch = circuit.add_gate(gate(GateType::Switch));
+        let and = circuit.add_gate(gate(GateType::And));
+        circuit.set_inputs(and, 3);
+        circuit.connect(Pin::new(switch, 0), Pin::new(and, 2));
+
+        let command = Command::set_inputs(&circuit, and, 2).expect("count changes");
+        history.execute(command, &mut circuit);
+        assert!(circuit.cables.is_empty());
+        history.undo(&mut circuit);
+        assert_eq!(circuit.gate(and).expect("gate exists").inputs(), 3);
+        assert_eq!(circuit.cables.len(), 1);
+    }
+
+    #[test]
+    fn register_width_keeps_load_and_clock_cables() {
+        let mut circuit = Circuit::new();
+        let mut history = History::default();
+        let switch = circuit.add_gate(gate(GateType::Switch));
+        let register = circuit.add_gate(gate(GateType::Register));
+        // D0, D1, LOAD, CLK
+        circuit.set_width(register, 2);
+        circuit.connect(Pin::new(switch, 0), Pin::new(register, 1));
+        let clock = circuit
+            .connect(Pin::new(switch, 0), Pin::new(register, 3))
+            .expect("cable fits");
+
+        let command = Command::set_width(&circuit, register, 1).expect("width changes");
+        history.execute(command, &mut circuit);
+        assert_eq!(circuit.gate(register).expect("gate exists").inputs(), 3);
+        assert_eq!(circuit.cables.len(), 1);
+        assert_eq!(circuit.cable(clock).map(|x| x.end.index), Some(2));
+
+        history.undo(&mut circuit);
+        assert_eq!(circuit.cables.len(), 2);
+        assert_eq!(circuit.cable(clock).map(|x| x.end.index), Some(3));
+    }
+
+    #[test]
+    fn oldest_edits_fall_off_the_limit() {
+        let mut circuit = Circuit::new();
+        let mut history = History::new(2);
+        for _ in 0..3 {
+            history.execute(Command::AddGate(gate(GateType::Not)), &mut circuit);
+        }
+        assert!(history.undo(&mut circuit).is_some());
+        assert!(history.undo(&mut circuit).is_some());
+        assert!(history.undo(&mut circuit).is_none());
+    }
+}
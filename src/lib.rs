@@ -0,0 +1,19 @@
+pub mod behavior;
+pub mod bus;
+pub mod cable;
+pub mod circuit;
+pub mod custom;
+pub mod gate;
+pub mod history;
+pub mod library;
+pub mod operations;
+pub mod routing;
+pub mod selection;
+pub mod sequential;
+pub mod signal;
+pub mod simulation;
+pub mod storage;
+pub mod truth_table;
+
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,99 @@
+use crate::behavior::Builtin;
+use crate::circuit::{Circuit, Pin};
+use crate::custom::CustomGate;
+use crate::gate::{Gate, GateId, GateType, Position};
+use std::rc::Rc;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+/// Places a built-in gate at `x`, `y` and returns its id.
+pub(crate) fn builtin(circuit: &mut Circuit, gatetype: GateType, x: i32, y: i32) -> GateId {
+    let behavior = Builtin::for_type(gatetype, 2).expect("built-in gate type");
+    circuit.add_gate(Gate::new(
+        gatetype,
+        " ",
+        Position::new(x, y),
+        WIDTH,
+        HEIGHT,
+        Box::new(behavior),
+    ))
+}
+
+fn instance(circuit: &mut Circuit, definition: &Rc<CustomGate>, x: i32, y: i32) -> GateId {
+    circuit.add_gate(Gate::custom(
+        definition.clone(),
+        Position::new(x, y),
+        WIDTH,
+        HEIGHT,
+    ))
+}
+
+/// Lays a cable from output `output` of `start` to input `input` of `end`.
+pub(crate) fn wire(circuit: &mut Circuit, start: GateId, output: usize, end: GateId, input: usize) {
+    circuit.connect(Pin::new(start, output), Pin::new(end, input));
+}
+
+/// A + B -> SUM, CARRY
+pub fn half_adder() -> CustomGate {
+    let mut circuit = Circuit::new();
+    let a = builtin(&mut circuit, GateType::Switch, 100, 100);
+    let b = builtin(&mut circuit, GateType::Switch, 100, 200);
+    let xor = builtin(&mut circuit, GateType::XOr, 250, 100);
+    let and = builtin(&mut circuit, GateType::And, 250, 200);
+    let sum = builtin(&mut circuit, GateType::Lamp, 400, 100);
+    let carry = builtin(&mut circuit, GateType::Lamp, 400, 200);
+
+    wire(&mut circuit, a, 0, xor, 0);
+    wire(&mut circuit, b, 0, xor, 1);
+    wire(&mut circuit, a, 0, and, 0);
+    wire(&mut circuit, b, 0, and, 1);
+    wire(&mut circuit, xor, 0, sum, 0);
+    wire(&mut circuit, and, 0, carry, 0);
+
+    CustomGate::from_circuit("HALF", &circuit).expect("half adder is not recursive")
+}
+
+/// A + B + CARRY IN -> SUM, CARRY OUT, built from two half adders.
+pub fn full_adder() -> CustomGate {
+    let half_adder = Rc::new(half_adder());
+
+    let mut circuit = Circuit::new();
+    let a = builtin(&mut circuit, GateType::Switch, 100, 100);
+    let b = builtin(&mut circuit, GateType::Switch, 100, 200);
+    let carry_in = builtin(&mut circuit, GateType::Switch, 100, 300);
+    let first = instance(&mut circuit, &half_adder, 250, 150);
+    let second = instance(&mut circuit, &half_adder, 400, 200);
+    let or = builtin(&mut circuit, GateType::Or, 550, 250);
+    let sum = builtin(&mut circuit, GateType::Lamp, 700, 100);
+    let carry_out = builtin(&mut circuit, GateType::Lamp, 700, 250);
+
+    wire(&mut circuit, a, 0, first, 0);
+    wire(&mut circuit, b, 0, first, 1);
+    wire(&mut circuit, first, 0, second, 0);
+    wire(&mut circuit, carry_in, 0, second, 1);
+    wire(&mut circuit, first, 1, or, 0);
+    wire(&mut circuit, second, 1, or, 1);
+    wire(&mut circuit, second, 0, sum, 0);
+    wire(&mut circuit, or, 0, carry_out, 0);
+
+    CustomGate::from_circuit("ADD", &circuit).expect("full adder is not recursive")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::behavior::GateBehavior;
+    use crate::custom::Composite;
+
+    #[test]
+    fn full_adder_adds_its_three_inputs() {
+        let mut adder = Composite::new(Rc::new(full_adder()));
+        for inputs in 0..8u32 {
+            let bits: Vec<bool> = (0..3).map(|x| inputs >> x & 1 == 1).collect();
+            let outputs = adder.evaluate(&bits);
+            let total = outputs[0] as u32 + 2 * outputs[1] as u32;
+            assert_eq!(total, inputs.count_ones(), "inputs {:?}", bits);
+        }
+    }
+}
@@ -1,20 +1,32 @@
-mod cable;
 mod drawing;
-mod gate;
-mod operations;
 
-use crate::cable::*;
-use crate::gate::*;
-use crate::operations::*;
+use game::behavior::{self, Builtin, Clock, DEFAULT_CLOCK_PERIOD};
+use game::bus::MAX_BUS_WIDTH;
+use game::cable::CableId;
+use game::circuit::{Circuit, Pin};
+use game::custom::CustomGate;
+use game::gate::*;
+use game::history::{Command, History};
+use game::library;
+use game::operations::*;
+use game::routing;
+use game::selection::{Axis, Selection};
+use game::sequential::DEFAULT_REGISTER_WIDTH;
+use game::simulation;
+use game::storage;
+use game::truth_table::TruthTable;
 
-use drawing::match_create_pos;
-use sdl2::event::Event;
+use drawing::{match_create_pos, Camera, Textures};
+use sdl2::event::{Event, WindowEvent};
 use sdl2::image::{self, InitFlag, LoadTexture};
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::Color;
-use sdl2::rect::{Point, Rect};
+use sdl2::video::FullscreenType;
 use std::collections::HashMap;
+use std::env;
+use std::fs;
 use std::path::Path;
+use std::rc::Rc;
 use std::time::Duration;
 
 pub const EERIE_BLACK: Color = Color::RGB(19, 21, 21);
@@ -26,256 +38,531 @@ pub const SNOW: Color = Color::RGB(255, 250, 251);
 pub const SPRITE_HEIGHT: u32 = 64;
 pub const SPRITE_WIDTH: u32 = 64;
 
-pub const DEFAULT_LEN_MENUITEMS: i32 = 8;
+pub const DEFAULT_LEN_MENUITEMS: i32 = 22;
 
-fn positions(gates: &[Gate]) -> Vec<Point> {
-    gates.iter().map(|x| x.position).collect()
+pub const MAX_CLOCK_PERIOD: u32 = 60 * 60;
+
+pub const MAX_LEN_GATENAME: usize = 8;
+pub const MAX_LEN_WIDTH: usize = 2;
+pub const MAX_LEN_PITCH: usize = 3;
+pub const MAX_LEN_RATE: usize = 8;
+pub const MAX_LEN_PATH: usize = 64;
+pub const WINDOW_TITLE: &str = "Logical Gates Simulator - ohnchen";
+pub const DEFAULT_PATH: &str = "circuit.lsim";
+pub const DEFAULT_EXPORT_PATH: &str = "truth_table.csv";
+pub const RECOVERY_PATH: &str = "recovery.lsim";
+pub const AUTOSAVE_SECONDS: u32 = 30;
+pub const DUPLICATE_OFFSET: i32 = 20;
+pub const ZOOM_STEP: f32 = 1.1;
+pub const MIN_WINDOW_WIDTH: u32 = 640;
+pub const MIN_WINDOW_HEIGHT: u32 = 480;
+/// Custom gates are added to the palette while it takes less than this share of the window
+/// height. The built-in gates are always shown.
+pub const MAX_MENU_SHARE: f32 = 0.4;
+pub const DEFAULT_GRID_PITCH: u32 = 16;
+pub const MAX_GRID_PITCH: u32 = 256;
+
+/// What the text typed into the prompt next to the CREATE button is used for.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Prompt {
+    GateName,
+    Save,
+    Open,
+    Export,
+    Width(GateId),
+    /// The half period of a clock in ticks, or its frequency with a `Hz` suffix.
+    ClockRate(GateId),
+    GridPitch,
+    /// Asks before everything on the canvas is removed; nothing is typed.
+    ConfirmClear,
+    /// Offers the autosaved circuit of a session that did not end cleanly.
+    Restore,
+}
+
+impl Prompt {
+    fn max_len(self) -> usize {
+        match self {
+            Prompt::GateName => MAX_LEN_GATENAME,
+            Prompt::Save | Prompt::Open | Prompt::Export => MAX_LEN_PATH,
+            Prompt::Width(_) => MAX_LEN_WIDTH,
+            Prompt::ClockRate(_) => MAX_LEN_RATE,
+            Prompt::GridPitch => MAX_LEN_PITCH,
+            Prompt::ConfirmClear | Prompt::Restore => 0,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Prompt::GateName => "name:",
+            Prompt::Save => "save:",
+            Prompt::Open => "open:",
+            Prompt::Export => "export:",
+            Prompt::Width(_) => "bits:",
+            Prompt::ClockRate(_) => "ticks or Hz:",
+            Prompt::GridPitch => "grid:",
+            Prompt::ConfirmClear => "clear canvas? Return: yes, Esc: no",
+            Prompt::Restore => "restore unsaved circuit? Return: yes, Esc: no",
+        }
+    }
+}
+
+/// Gates following the mouse since it was pressed at `origin`, each with the position it
+/// started from, and the waypoints of the cables between them. `placing` is set for a gate
+/// just taken from the palette.
+struct Drag {
+    origin: Position,
+    gates: Vec<(GateId, Position)>,
+    cables: Vec<(CableId, Vec<Position>)>,
+    placing: bool,
 }
 
-fn new_name() -> &'static str {
-    "I"
+/// A waypoint following the mouse, with the waypoints its cable had before and when the
+/// mouse was pressed. Let go over an input, it branches a new cable off there instead.
+struct Bend {
+    cable: CableId,
+    index: usize,
+    from: Vec<Position>,
+    pressed: Vec<Position>,
 }
 
-fn count_occurences(gates: &[Gate], gatetype: GateType) -> usize {
-    gates.iter().filter(|&x| x.gatetype == gatetype).count()
+/// Everything cable routes depend on, to route again only after it changed.
+#[derive(PartialEq, Default)]
+struct Layout {
+    gates: HashMap<GateId, (Position, u32, u32, usize, usize)>,
+    cables: HashMap<CableId, (Pin, Pin, Vec<Position>)>,
 }
 
-// I have no idea how to make this work
-fn compressed_func() -> fn(&[bool]) -> Vec<bool> {
-    and_func
+impl Layout {
+    fn of(circuit: &Circuit) -> Self {
+        Layout {
+            gates: circuit
+                .gates
+                .iter()
+                .map(|x| {
+                    (
+                        x.id,
+                        (x.position, x.width, x.height, x.inputs(), x.outputs()),
+                    )
+                })
+                .collect(),
+            cables: circuit
+                .cables
+                .iter()
+                .map(|x| (x.id, (x.start, x.end, x.waypoints.clone())))
+                .collect(),
+        }
+    }
+
+    /// The cables of `circuit` whose routes may be out of date since `self` was taken: new,
+    /// unrouted and edited ones, those on gates that moved, changed or went away, and those
+    /// running where such a gate was or now is.
+    fn stale(&self, now: &Layout, circuit: &Circuit) -> Vec<CableId> {
+        let mut changed: Vec<GateId> = Vec::new();
+        let mut bodies: Vec<(Position, u32, u32)> = Vec::new();
+        for (id, gate) in self.gates.iter() {
+            if now.gates.get(id) != Some(gate) {
+                changed.push(*id);
+                bodies.push((gate.0, gate.1, gate.2));
+            }
+        }
+        for (id, gate) in now.gates.iter() {
+            if self.gates.get(id) != Some(gate) {
+                changed.push(*id);
+                bodies.push((gate.0, gate.1, gate.2));
+            }
+        }
+
+        circuit
+            .cables
+            .iter()
+            .filter(|x| {
+                x.route.is_empty()
+                    || self.cables.get(&x.id) != now.cables.get(&x.id)
+                    || changed.contains(&x.start.gate)
+                    || changed.contains(&x.end.gate)
+                    || bodies.iter().any(|&(position, width, height)| {
+                        routing::passes(&x.route, position, width, height)
+                    })
+            })
+            .map(|x| x.id)
+            .collect()
+    }
+}
+
+/// Clock frequency at the front end's tick rate, e.g. `1Hz` for a period of 30 ticks.
+fn clock_label(period: u32) -> String {
+    let hertz = simulation::TICKS_PER_SECOND as f32 / (2 * period) as f32;
+    if hertz.fract() == 0.0 {
+        format!("{}Hz", hertz)
+    } else {
+        format!("{:.2}Hz", hertz)
+    }
+}
+
+/// The half period in ticks for a clock rate typed as ticks, e.g. `30`, or as a frequency,
+/// e.g. `2Hz` or `0.5hz`. Frequencies are rounded to the nearest period the tick rate allows.
+fn parse_clock_rate(input: &str) -> Option<u32> {
+    let lower = input.to_ascii_lowercase();
+    let period = match lower.strip_suffix("hz") {
+        Some(hertz) => {
+            let hertz: f32 = hertz.trim().parse().ok()?;
+            if !hertz.is_finite() || hertz <= 0.0 {
+                return None;
+            }
+            (simulation::TICKS_PER_SECOND as f32 / (2.0 * hertz))
+                .round()
+                .clamp(1.0, MAX_CLOCK_PERIOD as f32) as u32
+        }
+        None => lower.parse().ok()?,
+    };
+    (1..=MAX_CLOCK_PERIOD).contains(&period).then_some(period)
+}
+
+/// Moves every palette entry to its place in a window of the given size, built-in gates
+/// first and custom gates after them, wrapping onto more rows when a row is full.
+fn layout_palette(palette: &mut [Gate], width: u32, height: u32) {
+    let items = palette.len();
+    for (index, gate) in palette.iter_mut().enumerate() {
+        gate.position = drawing::menu_position(index, items, width, height);
+    }
+}
+
+/// The top edge of the palette, above which the circuit is shown.
+fn menu_top(palette: &[Gate], width: u32, height: u32) -> i32 {
+    height as i32 - drawing::menu_height(palette.len(), width) as i32
+}
+
+/// A palette entry for a custom gate, placed by the next `layout_palette`.
+fn custom_menuitem(definition: Rc<CustomGate>) -> Gate {
+    let max_connections = definition.inputs.len().max(definition.outputs.len());
+    Gate::custom(
+        definition,
+        Position::default(),
+        SPRITE_WIDTH,
+        height_for(max_connections),
+    )
+}
+
+/// Whether the palette can take another custom gate in a window of the given size without
+/// growing past `MAX_MENU_SHARE` of its height, or past the rows of the built-in gates.
+fn palette_has_room(len: usize, width: u32, height: u32) -> bool {
+    let limit = (height as f32 * MAX_MENU_SHARE) as u32;
+    let builtin = drawing::menu_height(DEFAULT_LEN_MENUITEMS as usize, width);
+    drawing::menu_height(len + 1, width) <= limit.max(builtin)
+}
+
+/// Lays a cable or removes the existing one between the two pins, recording the edit.
+fn toggle_cable(history: &mut History, circuit: &mut Circuit, output: Pin, input: Pin) {
+    if let Some(existing) = circuit
+        .cables
+        .iter()
+        .find(|x| x.start == output && x.end == input)
+        .cloned()
+    {
+        history.execute(Command::Disconnect(existing), circuit);
+    } else if let Some(cable) = circuit
+        .connect(output, input)
+        .and_then(|x| circuit.cable(x))
+    {
+        history.push(Command::Connect(cable.clone()));
+    }
+}
+
+/// Records the gates placed or moved and the bend points shifted by a finished drag.
+fn record_drag(drag: Drag, history: &mut History, circuit: &Circuit) {
+    let mut commands: Vec<Command> = drag
+        .gates
+        .iter()
+        .filter_map(|(id, from)| {
+            let gate = circuit.gate(*id)?;
+            if drag.placing {
+                Some(Command::AddGate(gate.clone()))
+            } else if gate.position != *from {
+                Some(Command::MoveGate {
+                    id: *id,
+                    from: *from,
+                    to: gate.position,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+    commands.extend(drag.cables.into_iter().filter_map(|(id, from)| {
+        let to = circuit.cable(id)?.waypoints.clone();
+        (to != from).then_some(Command::SetWaypoints { id, from, to })
+    }));
+    if !commands.is_empty() {
+        history.push(Command::Batch(commands));
+    }
+}
+
+/// Records the new bend points of a cable once one of them has been dragged.
+fn record_bend(bend: Bend, history: &mut History, circuit: &Circuit) {
+    if let Some(to) = circuit
+        .cable(bend.cable)
+        .map(|x| x.waypoints.clone())
+        .filter(|x| *x != bend.from)
+    {
+        history.push(Command::SetWaypoints {
+            id: bend.cable,
+            from: bend.from,
+            to,
+        });
+    }
+}
+
+/// Whether a new custom gate may be called `name`: no palette entry and no definition used
+/// by the palette or the circuit has that name yet.
+fn name_is_free(palette: &[Gate], circuit: &Circuit, name: &str) -> bool {
+    !palette.iter().any(|x| x.gatename == name)
+        && !storage::collect_definitions(circuit, &definitions(palette))
+            .iter()
+            .any(|x| x.name == name)
+}
+
+/// Loads a circuit file and puts its custom gates into the palette in place of the old ones.
+/// Custom gates built into the palette, or nested inside one, stay out of it.
+fn open(path: &Path, palette: &mut Vec<Gate>, width: u32, height: u32) -> Result<Circuit, String> {
+    let (mut circuit, loaded) = storage::load(path)?;
+    let builtin = storage::collect_definitions(
+        &Circuit::new(),
+        &definitions(&palette[..DEFAULT_LEN_MENUITEMS as usize]),
+    );
+    let loaded = storage::adopt_definitions(&mut circuit, loaded, &builtin)?;
+    palette.truncate(DEFAULT_LEN_MENUITEMS as usize);
+    for definition in loaded {
+        if !palette_has_room(palette.len(), width, height) {
+            eprintln!("no room in the palette for {}", definition.name);
+            continue;
+        }
+        palette.push(custom_menuitem(definition));
+    }
+    layout_palette(palette, width, height);
+    Ok(circuit)
+}
+
+/// Deletes the autosaved circuit, if there is one.
+fn remove_recovery() -> std::io::Result<()> {
+    fs::remove_file(RECOVERY_PATH).or_else(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Ok(()),
+        _ => Err(e),
+    })
+}
+
+fn definitions(palette: &[Gate]) -> Vec<Rc<CustomGate>> {
+    palette
+        .iter()
+        .filter_map(|x| x.behavior.definition().cloned())
+        .collect()
 }
 
 fn main() -> Result<(), String> {
+    let path_argument = env::args().nth(1);
+    let mut file_path = path_argument.clone().unwrap_or(DEFAULT_PATH.to_string());
+
     let sdl_context = sdl2::init()?;
     let video_subsystem = sdl_context.video()?;
 
     let _image_context = image::init(InitFlag::PNG | InitFlag::JPG)?;
 
-    let window = video_subsystem
-        .window("Logical Gates Simulator - ohnchen", 1280, 720)
+    let mut window = video_subsystem
+        .window(&format!("{} - {}", WINDOW_TITLE, file_path), 1280, 720)
         .position_centered()
+        .resizable()
         .build()
         .expect("could not initialize video subsystem");
+    window
+        .set_minimum_size(MIN_WINDOW_WIDTH, MIN_WINDOW_HEIGHT)
+        .map_err(|e| e.to_string())?;
+    // SDL starts with text input on, which would type the key opening a prompt into it
+    video_subsystem.text_input().stop();
 
     let mut canvas = window
         .into_canvas()
         .build()
         .expect("could not make a canvas");
 
-    let (width, height) = canvas.output_size()?;
-
-    let mut gates: Vec<Gate> = Vec::new();
-    let mut cables: Vec<Cable> = Vec::new();
+    let (mut width, mut height) = canvas.output_size()?;
 
-    let mut inputs: HashMap<Point, Vec<Point>> = HashMap::new();
-    let mut outputs: HashMap<Point, Vec<Point>> = HashMap::new();
+    let mut circuit = Circuit::new();
 
     let texture_creator = canvas.texture_creator();
-    let switch_texture = texture_creator.load_texture("assets/switch_placeholder.png")?;
-    let and_placeholder = texture_creator.load_texture("assets/and_placeholder.png")?;
-    let or_placeholder = texture_creator.load_texture("assets/or_placeholder.png")?;
-    let not_placeholder = texture_creator.load_texture("assets/not_placeholder.png")?;
-    let nand_placeholder = texture_creator.load_texture("assets/nand_placeholder.png")?;
-    let xor_placeholder = texture_creator.load_texture("assets/xor_placeholder.png")?;
+    let textures = Textures {
+        switch: texture_creator.load_texture("assets/switch_placeholder.png")?,
+        and: texture_creator.load_texture("assets/and_placeholder.png")?,
+        or: texture_creator.load_texture("assets/or_placeholder.png")?,
+        not: texture_creator.load_texture("assets/not_placeholder.png")?,
+        nand: texture_creator.load_texture("assets/nand_placeholder.png")?,
+        xor: texture_creator.load_texture("assets/xor_placeholder.png")?,
+        nor: texture_creator.load_texture("assets/nor_placeholder.png")?,
+        xnor: texture_creator.load_texture("assets/xnor_placeholder.png")?,
+        buffer: texture_creator.load_texture("assets/buffer_placeholder.png")?,
+        parity: texture_creator.load_texture("assets/parity_placeholder.png")?,
+    };
 
     let ttf_context = sdl2::ttf::init().map_err(|e| e.to_string())?;
     let font_path: &Path = Path::new(&"font/NotoSansCJK-Regular.ttc");
     let mut font = ttf_context.load_font(font_path, 128)?;
     font.set_style(sdl2::ttf::FontStyle::NORMAL);
 
-    let normal_rect = Rect::new(0, 0, SPRITE_WIDTH, SPRITE_HEIGHT);
-
-    let default_switch_value = Some(0b1);
-    let default_lamp_value = None;
-    let default_value = None;
-
-    let mut new_gate: Gate;
-
-    let switch = Gate::new(
+    let mut switch = Gate::new(
         GateType::Switch,
         " ",
-        Point::new(38, height as i32 - 38),
-        &switch_texture,
-        normal_rect,
-        0,
-        1,
-        switch_lamp_func,
-        default_switch_value,
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Builtin::new(0, 1, switch_lamp_func)),
     );
+    switch.set_switch(true);
     let and_gate = Gate::new(
         GateType::And,
         "AND",
-        Point::new(38 + 66, height as i32 - 38),
-        &and_placeholder,
-        normal_rect,
-        2,
-        1,
-        and_func,
-        default_value,
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Builtin::new(2, 1, and_func)),
     );
     let or_gate = Gate::new(
         GateType::Or,
         "OR",
-        Point::new(38 + 2 * 66, height as i32 - 38),
-        &or_placeholder,
-        normal_rect,
-        2,
-        1,
-        or_func,
-        default_value,
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Builtin::new(2, 1, or_func)),
     );
     let xor_gate = Gate::new(
         GateType::XOr,
         "XOR",
-        Point::new(38 + 3 * 66, height as i32 - 38),
-        &xor_placeholder,
-        normal_rect,
-        2,
-        1,
-        xor_func,
-        default_value,
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Builtin::new(2, 1, xor_func)),
     );
     let nand_gate = Gate::new(
         GateType::Nand,
         "NAND",
-        Point::new(38 + 4 * 66, height as i32 - 38),
-        &nand_placeholder,
-        normal_rect,
-        2,
-        1,
-        nand_func,
-        default_value,
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Builtin::new(2, 1, nand_func)),
     );
     let not_gate = Gate::new(
         GateType::Not,
         "NOT",
-        Point::new(38 + 5 * 66, height as i32 - 38),
-        &not_placeholder,
-        normal_rect,
-        1,
-        1,
-        not_func,
-        default_value,
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Builtin::new(1, 1, not_func)),
     );
     let lamp = Gate::new(
         GateType::Lamp,
         " ",
-        Point::new(38 + 6 * 66, height as i32 - 38),
-        &switch_texture,
-        normal_rect,
-        1,
-        0,
-        switch_lamp_func,
-        default_lamp_value,
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Builtin::new(1, 0, switch_lamp_func)),
     );
-    let two_outputs_gate = Gate::new(
-        GateType::Not,
-        "ADD",
-        Point::new(38 + 7 * 66, height as i32 - 38),
-        &not_placeholder,
-        normal_rect,
-        3,
-        2,
-        add_func,
-        default_value,
+    let add_gate = Gate::custom(
+        Rc::new(library::full_adder()),
+        Position::default(),
+        SPRITE_WIDTH,
+        height_for(3),
+    );
+    let clock = Gate::new(
+        GateType::Clock,
+        &clock_label(DEFAULT_CLOCK_PERIOD),
+        Position::default(),
+        SPRITE_WIDTH,
+        SPRITE_HEIGHT,
+        Box::new(Clock::new(DEFAULT_CLOCK_PERIOD)),
     );
 
-    let mut gatetypes_menuitems = vec![
-        switch.gatetype,
-        and_gate.gatetype,
-        or_gate.gatetype,
-        nand_gate.gatetype,
-        xor_gate.gatetype,
-        not_gate.gatetype,
-        lamp.gatetype,
-        two_outputs_gate.gatetype,
-    ];
-    let mut gatenames_menuitems = vec![
-        switch.gatename,
-        and_gate.gatename,
-        or_gate.gatename,
-        nand_gate.gatename,
-        xor_gate.gatename,
-        not_gate.gatename,
-        lamp.gatename,
-        two_outputs_gate.gatename,
-    ];
-    let mut positions_menuitems = vec![
-        switch.position,
-        and_gate.position,
-        or_gate.position,
-        nand_gate.position,
-        xor_gate.position,
-        not_gate.position,
-        lamp.position,
-        two_outputs_gate.position,
-    ];
-    let mut textures_menuitems = vec![
-        switch.texture,
-        and_gate.texture,
-        or_gate.texture,
-        nand_gate.texture,
-        xor_gate.texture,
-        not_gate.texture,
-        lamp.texture,
-        two_outputs_gate.texture,
-    ];
-    let mut sprite_menuitems = vec![normal_rect; 8];
-    let mut inputs_menuitems = vec![
-        switch.inputs,
-        and_gate.inputs,
-        or_gate.inputs,
-        nand_gate.inputs,
-        xor_gate.inputs,
-        not_gate.inputs,
-        lamp.inputs,
-        two_outputs_gate.inputs,
-    ];
-    let mut outputs_menuitems = vec![
-        switch.outputs,
-        and_gate.outputs,
-        or_gate.outputs,
-        nand_gate.outputs,
-        xor_gate.outputs,
-        not_gate.outputs,
-        lamp.outputs,
-        two_outputs_gate.outputs,
-    ];
-    let mut functions_menuitems = vec![
-        switch.comp_func,
-        and_gate.comp_func,
-        or_gate.comp_func,
-        nand_gate.comp_func,
-        xor_gate.comp_func,
-        not_gate.comp_func,
-        lamp.comp_func,
-        two_outputs_gate.comp_func,
-    ];
-    let mut input_values_menuitems = vec![
-        switch.input_values,
-        and_gate.input_values,
-        or_gate.input_values,
-        nand_gate.input_values,
-        xor_gate.input_values,
-        not_gate.input_values,
-        lamp.input_values,
-        two_outputs_gate.input_values,
+    let mut palette = vec![
+        switch, and_gate, or_gate, nand_gate, xor_gate, not_gate, lamp, add_gate, clock,
     ];
+    for (gatetype, gatename) in [
+        (GateType::Nor, "NOR"),
+        (GateType::XNor, "XNOR"),
+        (GateType::Buffer, "BUF"),
+        (GateType::Parity, "PAR"),
+    ] {
+        let behavior = Builtin::for_type(gatetype, 2)
+            .ok_or_else(|| format!("{} has no built-in behaviour", gatetype))?;
+        palette.push(Gate::new(
+            gatetype,
+            gatename,
+            Position::default(),
+            SPRITE_WIDTH,
+            SPRITE_HEIGHT,
+            Box::new(behavior),
+        ));
+    }
+    for (gatetype, gatename) in [
+        (GateType::SrLatch, "SR"),
+        (GateType::DLatch, "D-L"),
+        (GateType::DFlipFlop, "D-FF"),
+        (GateType::JkFlipFlop, "JK"),
+        (GateType::TFlipFlop, "T-FF"),
+        (GateType::Register, "REG"),
+        (GateType::Splitter, "SPLIT"),
+        (GateType::Merger, "MERGE"),
+        (GateType::TriState, "TRI"),
+    ] {
+        let behavior = behavior::for_type(gatetype, DEFAULT_REGISTER_WIDTH + 2)
+            .ok_or_else(|| format!("{} has no built-in behaviour", gatetype))?;
+        let max_connections = behavior.inputs().max(behavior.outputs());
+        palette.push(Gate::new(
+            gatetype,
+            gatename,
+            Position::default(),
+            SPRITE_WIDTH,
+            height_for(max_connections),
+            behavior,
+        ));
+    }
+    layout_palette(&mut palette, width, height);
+
+    // a file named on the command line is opened right away; one that does not exist yet
+    // is created by the first save
+    if let Some(path) = path_argument.filter(|x| Path::new(x).exists()) {
+        match open(Path::new(&path), &mut palette, width, height) {
+            Ok(loaded) => circuit = loaded,
+            Err(e) => eprintln!("could not open {}: {}", path, e),
+        }
+    }
 
     canvas.set_draw_color(JET);
     canvas.clear();
 
     let mut event_pump = sdl_context.event_pump()?;
-    let mut moved_new = false;
-    let mut moved_old = false;
-    let mut moved_old_index: usize = usize::MAX;
-
-    let mut start_point_cable: Point = Point::new(0, 0);
-    let mut end_point_cable: Point;
-    let mut cable_is_on: bool = false;
-
-    let mut input_points = Vec::new();
-    let mut output_points = Vec::new();
+    let mut drag: Option<Drag> = None;
+    let mut bend: Option<Bend> = None;
+    let mut routed = Layout::default();
+    let mut selection = Selection::default();
+    // corner where a rubber band selection started and whether it adds to the selection
+    let mut band: Option<(Position, bool)> = None;
+    let mut camera = Camera::default();
+    // where the mouse was when the view was last moved, while panning
+    let mut pan: Option<Position> = None;
+    let mut space_held = false;
+    // gates snap to the grid while it is shown
+    let mut grid_shown = true;
+    let mut grid_pitch = DEFAULT_GRID_PITCH;
+    let mut history = History::default();
+    let mut start_pin_cable = None;
+    let mut prompt: Option<(Prompt, String)> = None;
+    let mut truth_table: Option<TruthTable> = None;
+    let mut truth_table_scroll: usize = 0;
+    let mut paused = false;
+    let mut single_step = false;
+    // what the last save or open wrote or read, to tell whether there is unsaved work
+    let mut saved_text = storage::serialize(&circuit, &definitions(&palette));
+    let mut autosaved_text = saved_text.clone();
+    let mut autosave_ticks = 0;
+    if Path::new(RECOVERY_PATH).exists() {
+        prompt = Some((Prompt::Restore, String::new()));
+    }
 
     'running: loop {
         let mouse_pos_x = event_pump.mouse_state().x();
@@ -283,339 +570,851 @@ fn main() -> Result<(), String> {
 
         for event in event_pump.poll_iter() {
             match event {
+                Event::TextInput { text, .. } if prompt.is_some() => {
+                    if let Some((kind, input)) = prompt.as_mut() {
+                        for c in text.chars() {
+                            if input.chars().count() < kind.max_len() {
+                                input.push(c);
+                            }
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if prompt.is_some() => {
+                    if let Some((_, input)) = prompt.as_mut() {
+                        input.pop();
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } if prompt.is_some() => {
+                    prompt = None;
+                    video_subsystem.text_input().stop();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Return),
+                    ..
+                } if prompt.is_some() => {
+                    let (kind, input) = prompt.take().unwrap_or((Prompt::GateName, String::new()));
+                    let input = input.trim();
+                    video_subsystem.text_input().stop();
+
+                    match kind {
+                        Prompt::GateName if input.is_empty() => {}
+                        Prompt::GateName if !name_is_free(&palette, &circuit, input) => {
+                            eprintln!("could not create {}: the name is already used", input);
+                        }
+                        Prompt::GateName => match CustomGate::from_circuit(input, &circuit) {
+                            Ok(definition) => {
+                                let definition = Rc::new(definition);
+                                palette.push(custom_menuitem(definition.clone()));
+                                layout_palette(&mut palette, width, height);
+                                history.push(Command::CreateCustomGate(definition));
+                            }
+                            Err(e) => eprintln!("could not create {}: {}", input, e),
+                        },
+                        Prompt::Save => {
+                            match storage::save(Path::new(input), &circuit, &definitions(&palette))
+                            {
+                                Ok(()) => {
+                                    file_path = input.to_string();
+                                    saved_text =
+                                        storage::serialize(&circuit, &definitions(&palette));
+                                    // the saved file now holds everything the recovery file did
+                                    if let Err(e) = remove_recovery() {
+                                        eprintln!("could not remove {}: {}", RECOVERY_PATH, e);
+                                    }
+                                    autosaved_text = saved_text.clone();
+                                }
+                                Err(e) => eprintln!("could not save {}: {}", input, e),
+                            }
+                        }
+                        Prompt::Open | Prompt::Restore => {
+                            let path = if kind == Prompt::Open {
+                                input
+                            } else {
+                                RECOVERY_PATH
+                            };
+                            match open(Path::new(path), &mut palette, width, height) {
+                                Ok(loaded) => {
+                                    circuit = loaded;
+                                    drag = None;
+                                    bend = None;
+                                    selection.clear();
+                                    start_pin_cable = None;
+                                    history.clear();
+                                    if kind == Prompt::Open {
+                                        file_path = input.to_string();
+                                        saved_text =
+                                            storage::serialize(&circuit, &definitions(&palette));
+                                    }
+                                }
+                                Err(e) => eprintln!("could not open {}: {}", path, e),
+                            }
+                        }
+                        Prompt::Export => {
+                            if let Some(table) = truth_table.as_ref() {
+                                let text = if input.ends_with(".md") {
+                                    table.to_markdown()
+                                } else {
+                                    table.to_csv()
+                                };
+                                if let Err(e) = fs::write(input, text) {
+                                    eprintln!("could not export {}: {}", input, e);
+                                }
+                            }
+                        }
+                        Prompt::Width(id) => match input.parse::<u8>() {
+                            Ok(bits) if (1..=MAX_BUS_WIDTH).contains(&bits) => {
+                                if let Some(command) = Command::set_width(&circuit, id, bits) {
+                                    history.execute(command, &mut circuit);
+                                }
+                                let set = circuit.gate(id).and_then(|x| x.behavior.width());
+                                if let Some(set) = set.filter(|x| *x != bits) {
+                                    eprintln!("this gate takes at most {} bits", set);
+                                }
+                            }
+                            _ => eprintln!("bus width must be 1 to {}", MAX_BUS_WIDTH),
+                        },
+                        Prompt::ClockRate(id) => match parse_clock_rate(input) {
+                            Some(period) => {
+                                if let Some(gate) = circuit.gate_mut(id) {
+                                    gate.behavior.set_period(period);
+                                    gate.gatename = clock_label(period);
+                                }
+                            }
+                            None => eprintln!(
+                                "clock rate must be 1 to {} ticks or a frequency like 2Hz",
+                                MAX_CLOCK_PERIOD
+                            ),
+                        },
+                        Prompt::GridPitch => match input.parse::<u32>() {
+                            Ok(pitch) if (2..=MAX_GRID_PITCH).contains(&pitch) => {
+                                grid_pitch = pitch;
+                            }
+                            _ => eprintln!("grid pitch must be 2 to {}", MAX_GRID_PITCH),
+                        },
+                        Prompt::ConfirmClear => {
+                            // undo brings the canvas back
+                            history.execute(Command::Clear(circuit.clone()), &mut circuit);
+                            drag = None;
+                            bend = None;
+                            selection.clear();
+                            start_pin_cable = None;
+                        }
+                    }
+                    canvas
+                        .window_mut()
+                        .set_title(&format!("{} - {}", WINDOW_TITLE, file_path))
+                        .map_err(|e| e.to_string())?;
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::S | Keycode::O)),
+                    keymod,
+                    ..
+                } if prompt.is_none() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let kind = if keycode == Keycode::S {
+                        Prompt::Save
+                    } else {
+                        Prompt::Open
+                    };
+                    prompt = Some((kind, file_path.clone()));
+                    video_subsystem.text_input().start();
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::Z | Keycode::Y)),
+                    keymod,
+                    ..
+                } if prompt.is_none() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    // a drag in progress is kept as it is and becomes the edit that is undone
+                    if let Some(drag) = drag.take() {
+                        record_drag(drag, &mut history, &circuit);
+                    }
+                    if let Some(bend) = bend.take() {
+                        record_bend(bend, &mut history, &circuit);
+                    }
+                    start_pin_cable = None;
+                    let command = if keycode == Keycode::Z {
+                        history.undo(&mut circuit)
+                    } else {
+                        history.redo(&mut circuit)
+                    };
+                    // custom gates live in the palette, which the history does not know about
+                    if let Some(Command::CreateCustomGate(definition)) = command {
+                        let existing = palette.iter().position(|x| {
+                            x.behavior
+                                .definition()
+                                .is_some_and(|x| Rc::ptr_eq(x, definition))
+                        });
+                        match existing {
+                            Some(index) => {
+                                palette.remove(index);
+                                layout_palette(&mut palette, width, height);
+                            }
+                            None if palette_has_room(palette.len(), width, height) => {
+                                palette.push(custom_menuitem(definition.clone()));
+                                layout_palette(&mut palette, width, height);
+                            }
+                            None => eprintln!("no room in the palette for {}", definition.name),
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::E),
+                    keymod,
+                    ..
+                } if prompt.is_none()
+                    && truth_table.is_some()
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    prompt = Some((Prompt::Export, DEFAULT_EXPORT_PATH.to_string()));
+                    video_subsystem.text_input().start();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::T),
+                    ..
+                } if prompt.is_none() => {
+                    if truth_table.take().is_none() {
+                        match TruthTable::of_circuit(&circuit) {
+                            Ok(table) => truth_table = Some(table),
+                            Err(e) => eprintln!("could not build truth table: {}", e),
+                        }
+                    }
+                    truth_table_scroll = 0;
+                }
+                Event::MouseWheel { y, .. } => {
+                    let hovered =
+                        drawing::match_mouse_pos(mouse_pos_x, mouse_pos_y, &circuit.gates, &camera)
+                            .map(|x| circuit.gates[x].id);
+                    // scrolling over a gate adds or removes input pins where it can, and
+                    // zooms like anywhere else over gates with a fixed number of inputs
+                    let resize = hovered.filter(|_| prompt.is_none()).and_then(|id| {
+                        let count = circuit.gate(id).map_or(0, |x| x.inputs());
+                        Command::set_inputs(&circuit, id, count.saturating_add_signed(y as isize))
+                    });
+                    if let Some(command) = resize {
+                        history.execute(command, &mut circuit);
+                    } else if let Some(table) = truth_table.as_ref().filter(|_| {
+                        drawing::truth_table_panel(
+                            width,
+                            height,
+                            drawing::menu_height(palette.len(), width),
+                        )
+                        .contains_point((mouse_pos_x, mouse_pos_y))
+                    }) {
+                        truth_table_scroll = (truth_table_scroll as i64 - y as i64 * 3)
+                            .clamp(0, table.rows.len().saturating_sub(1) as i64)
+                            as usize;
+                    } else if mouse_pos_y < menu_top(&palette, width, height) {
+                        camera.zoom_at(mouse_pos_x, mouse_pos_y, ZOOM_STEP.powi(y));
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } if prompt.is_none() => {
+                    space_held = true;
+                }
+                Event::KeyUp {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => {
+                    space_held = false;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::G),
+                    keymod,
+                    ..
+                } if prompt.is_none() => {
+                    if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) {
+                        prompt = Some((Prompt::GridPitch, grid_pitch.to_string()));
+                        video_subsystem.text_input().start();
+                    } else {
+                        grid_shown = !grid_shown;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } if prompt.is_none() => {
+                    camera.fit(
+                        &circuit,
+                        width,
+                        menu_top(&palette, width, height).max(0) as u32,
+                    );
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: sdl2::mouse::MouseButton::Middle,
+                    ..
+                } => {
+                    pan = Some(Position::new(mouse_pos_x, mouse_pos_y));
+                }
+                Event::MouseButtonDown {
+                    mouse_btn: sdl2::mouse::MouseButton::Left,
+                    ..
+                } if space_held && mouse_pos_y < menu_top(&palette, width, height) => {
+                    pan = Some(Position::new(mouse_pos_x, mouse_pos_y));
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: sdl2::mouse::MouseButton::Middle,
+                    ..
+                } => {
+                    pan = None;
+                }
+                Event::MouseButtonUp {
+                    mouse_btn: sdl2::mouse::MouseButton::Left,
+                    ..
+                } if pan.is_some() => {
+                    pan = None;
+                }
+                Event::Window {
+                    win_event: WindowEvent::Resized(..) | WindowEvent::SizeChanged(..),
+                    ..
+                } => {
+                    (width, height) = canvas.output_size()?;
+                    // entries already in the palette stay after a shrink, taking more rows
+                    // instead of overlapping, but no more are added until there is room
+                    layout_palette(&mut palette, width, height);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => {
+                    let fullscreen = match canvas.window().fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off,
+                    };
+                    canvas.window_mut().set_fullscreen(fullscreen)?;
+                }
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
                 } => {
+                    // a clean exit keeps the recovery file only for unsaved work
+                    let text = storage::serialize(&circuit, &definitions(&palette));
+                    let result = if matches!(prompt, Some((Prompt::Restore, _))) {
+                        Ok(())
+                    } else if text == saved_text {
+                        remove_recovery()
+                    } else {
+                        fs::write(RECOVERY_PATH, text)
+                    };
+                    if let Err(e) = result {
+                        eprintln!("could not update {}: {}", RECOVERY_PATH, e);
+                    }
                     break 'running;
                 }
                 Event::MouseButtonDown {
                     mouse_btn: sdl2::mouse::MouseButton::Left,
                     ..
                 } => {
-                    if mouse_pos_y > height as i32 - 64 {
+                    let mouse = camera.to_world(mouse_pos_x, mouse_pos_y);
+                    let shift = sdl_context
+                        .keyboard()
+                        .mod_state()
+                        .intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                    let hovered =
+                        drawing::match_mouse_pos(mouse_pos_x, mouse_pos_y, &circuit.gates, &camera)
+                            .map(|x| circuit.gates[x].id);
+                    start_pin_cable = drawing::match_mouse_pos_con(
+                        mouse_pos_x,
+                        mouse_pos_y,
+                        false,
+                        &circuit,
+                        16,
+                        16,
+                        &camera,
+                    );
+
+                    if mouse_pos_y > menu_top(&palette, width, height) {
                         if let Some(element) = drawing::match_mouse_pos(
                             mouse_pos_x,
                             mouse_pos_y,
-                            &positions_menuitems,
-                            64,
-                            64,
+                            &palette,
+                            &Camera::default(),
                         ) {
                             // [TODO] maybe implement that newly created gates can have other dimensions on
                             // the screen so they can have more than 3 inputs/outputs
-                            moved_new = true;
-                            gates.push(Gate::new(
-                                gatetypes_menuitems[element],
-                                gatenames_menuitems[element],
-                                Point::new(mouse_pos_x, mouse_pos_y),
-                                textures_menuitems[element],
-                                sprite_menuitems[element],
-                                inputs_menuitems[element],
-                                outputs_menuitems[element],
-                                functions_menuitems[element],
-                                input_values_menuitems[element],
-                            ));
+                            let mut new_gate = palette[element].clone();
+                            new_gate.position = mouse;
+                            let id = circuit.add_gate(new_gate);
+                            selection.clear();
+                            drag = Some(Drag {
+                                origin: mouse,
+                                gates: vec![(id, mouse)],
+                                cables: Vec::new(),
+                                placing: true,
+                            });
+                        }
+                    } else if start_pin_cable.is_some() {
+                        // laying a cable
+                    } else if shift {
+                        if let Some(id) = hovered {
+                            selection.toggle_gate(id);
+                        } else if let Some(id) = drawing::match_mouse_pos_cable(
+                            mouse_pos_x,
+                            mouse_pos_y,
+                            &circuit,
+                            6,
+                            &camera,
+                        ) {
+                            selection.toggle_cable(id);
+                        } else {
+                            band = Some((mouse, true));
                         }
-                    } else if let Some(element) = drawing::match_mouse_pos(
+                    } else if let Some((cable, index)) = drawing::match_mouse_pos_waypoint(
                         mouse_pos_x,
                         mouse_pos_y,
-                        &positions(&gates),
-                        64,
-                        64,
+                        &circuit,
+                        &camera,
                     ) {
-                        moved_old = true;
-                        moved_old_index = element;
-                    }
-                    if let Some((gate, element, output_pos)) = drawing::match_mouse_pos_con(
+                        let from = circuit
+                            .cable(cable)
+                            .map_or(Vec::new(), |x| x.waypoints.clone());
+                        bend = Some(Bend {
+                            cable,
+                            index,
+                            pressed: from.clone(),
+                            from,
+                        });
+                    } else if let Some(id) = hovered {
+                        if !selection.gates.contains(&id) {
+                            selection.clear();
+                            selection.gates.push(id);
+                        }
+                        drag = Some(Drag {
+                            origin: mouse,
+                            gates: selection
+                                .gates
+                                .iter()
+                                .filter_map(|x| circuit.gate(*x).map(|gate| (*x, gate.position)))
+                                .collect(),
+                            cables: circuit
+                                .cables
+                                .iter()
+                                .filter(|x| !x.waypoints.is_empty())
+                                .filter(|x| {
+                                    selection.gates.contains(&x.start.gate)
+                                        && selection.gates.contains(&x.end.gate)
+                                })
+                                .map(|x| (x.id, x.waypoints.clone()))
+                                .collect(),
+                            placing: false,
+                        });
+                    } else if let Some(cable) = drawing::match_mouse_pos_cable(
                         mouse_pos_x,
                         mouse_pos_y,
-                        false,
-                        &gates,
-                        16,
-                        16,
+                        &circuit,
+                        6,
+                        &camera,
                     ) {
-                        cable_is_on = gates[gate].output_is_on()[output_pos];
-                        start_point_cable = gates[gate].output_positions()[element];
+                        // clicking a wire bends it there
+                        let from = circuit
+                            .cable(cable)
+                            .map_or(Vec::new(), |x| x.waypoints.clone());
+                        let point = if grid_shown {
+                            mouse.snap(grid_pitch as i32)
+                        } else {
+                            mouse
+                        };
+                        if let Some(index) = circuit.add_waypoint(cable, point) {
+                            let pressed = circuit
+                                .cable(cable)
+                                .map_or(Vec::new(), |x| x.waypoints.clone());
+                            bend = Some(Bend {
+                                cable,
+                                index,
+                                from,
+                                pressed,
+                            });
+                        }
+                    } else {
+                        selection.clear();
+                        band = Some((mouse, false));
                     }
                 }
                 Event::MouseButtonUp {
                     mouse_btn: sdl2::mouse::MouseButton::Left,
                     ..
                 } => {
-                    moved_new = false;
-                    moved_old = false;
-                    if let Some((gate, element, _)) = drawing::match_mouse_pos_con(
+                    let mouse = camera.to_world(mouse_pos_x, mouse_pos_y);
+                    if let Some(drag) = drag.take() {
+                        record_drag(drag, &mut history, &circuit);
+                    }
+                    let input = drawing::match_mouse_pos_con(
                         mouse_pos_x,
                         mouse_pos_y,
                         true,
-                        &gates, // [ ]
+                        &circuit,
                         16,
                         16,
-                    ) {
-                        end_point_cable = gates[gate].input_positions()[element];
-
-                        if cable_is_on && gates[gate].input_values.is_some() {
-                            gates[gate].input_values =
-                                Some(gates[gate].input_values.unwrap() | 2u64.pow(element as u32));
-                        } else if cable_is_on {
-                            gates[gate].input_values = Some(2u64.pow(element as u32));
-                        } else if !cable_is_on && gates[gate].input_values.is_none() {
-                            gates[gate].input_values = Some(0);
-                        }
-
-                        if cables.contains(&Cable {
-                            state: State::On,
-                            start_point: start_point_cable,
-                            end_point: end_point_cable,
-                        }) || cables.contains(&Cable {
-                            state: State::Off,
-                            start_point: start_point_cable,
-                            end_point: end_point_cable,
-                        }) {
-                            cables.remove(
-                                cables
-                                    .iter()
-                                    .position(|&x| {
-                                        x.start_point == start_point_cable
-                                            && x.end_point == end_point_cable
-                                    })
-                                    .unwrap(),
-                            );
-                        } else if start_point_cable != end_point_cable
-                            && !cables.iter().any(|&x| x.end_point == end_point_cable)
+                        &camera,
+                    );
+                    if let Some((branch, input)) = input.and_then(|x| Some((bend.take()?, x))) {
+                        // the branch shares the bend points of the cable up to where it leaves
+                        circuit.set_waypoints(branch.cable, branch.pressed.clone());
+                        let output = circuit.cable(branch.cable).map(|x| x.start);
+                        if let Some(cable) = output
+                            .and_then(|x| circuit.connect(x, input))
+                            .and_then(|x| circuit.cable_mut(x))
                         {
-                            cables.push(Cable::new(
-                                cable_is_on,
-                                start_point_cable,
-                                end_point_cable,
-                            ));
+                            cable.waypoints = branch.pressed[..=branch.index].to_vec();
+                            let mut commands = vec![Command::Connect(cable.clone())];
+                            if branch.pressed != branch.from {
+                                commands.push(Command::SetWaypoints {
+                                    id: branch.cable,
+                                    from: branch.from.clone(),
+                                    to: branch.pressed.clone(),
+                                });
+                            }
+                            history.push(Command::Batch(commands));
+                        } else {
+                            circuit.set_waypoints(branch.cable, branch.from.clone());
                         }
                     }
+                    if let Some(bend) = bend.take() {
+                        record_bend(bend, &mut history, &circuit);
+                    }
+                    if let Some((corner, _)) = band.take() {
+                        selection.extend(Selection::in_rect(&circuit, corner, mouse));
+                    }
+                    if let (Some(start_pin), Some(end_pin)) = (
+                        start_pin_cable.take(),
+                        drawing::match_mouse_pos_con(
+                            mouse_pos_x,
+                            mouse_pos_y,
+                            true,
+                            &circuit,
+                            16,
+                            16,
+                            &camera,
+                        ),
+                    ) {
+                        toggle_cable(&mut history, &mut circuit, start_pin, end_pin);
+                    }
 
-                    if match_create_pos(&canvas, mouse_pos_x, mouse_pos_y, 50, 30)
-                        && positions_menuitems.len() < 11 + DEFAULT_LEN_MENUITEMS as usize
-                    {
-                        new_gate = Gate::new(
-                            GateType::Custom,
-                            new_name(), // [TODO] add functionality
-                            Point::new(
-                                width as i32
-                                    - 38
-                                    - (positions_menuitems.len() as i32 - DEFAULT_LEN_MENUITEMS)
-                                        * 66,
-                                height as i32 - 38,
-                            ),
-                            &or_placeholder,
-                            {
-                                let mut i = 1;
-                                let max_connections = if count_occurences(&gates, GateType::Switch)
-                                    >= count_occurences(&gates, GateType::Lamp)
-                                {
-                                    count_occurences(&gates, GateType::Switch)
-                                } else {
-                                    count_occurences(&gates, GateType::Lamp)
-                                };
-
-                                while max_connections > 2usize.pow(i) + 1 {
-                                    i += 1;
-                                }
-                                Rect::new(0, 0, SPRITE_WIDTH, SPRITE_HEIGHT * 2u32.pow(i - 1))
-                            },
-                            count_occurences(&gates, GateType::Switch),
-                            count_occurences(&gates, GateType::Lamp),
-                            compressed_func(), // [TODO] add functionality
-                            None,
-                        );
-                        gatetypes_menuitems.push(new_gate.gatetype);
-                        gatenames_menuitems.push(new_gate.gatename);
-                        positions_menuitems.push(new_gate.position);
-                        textures_menuitems.push(new_gate.texture);
-                        sprite_menuitems.push(new_gate.sprite);
-                        inputs_menuitems.push(new_gate.inputs);
-                        outputs_menuitems.push(new_gate.outputs);
-                        functions_menuitems.push(new_gate.comp_func);
-                        input_values_menuitems.push(new_gate.input_values);
+                    if match_create_pos(mouse_pos_x, mouse_pos_y, width) {
+                        if !palette_has_room(palette.len(), width, height) {
+                            eprintln!(
+                                "no room in the palette for another custom gate, \
+                                 make the window larger"
+                            );
+                        } else if circuit.count_occurences(GateType::Lamp) > 0 {
+                            prompt = Some((Prompt::GateName, String::new()));
+                            video_subsystem.text_input().start();
+                        }
                     }
                 }
                 Event::MouseButtonDown {
                     mouse_btn: sdl2::mouse::MouseButton::Right,
                     ..
                 } => {
-                    if let Some(element) = drawing::match_mouse_pos(
+                    if let Some((id, index)) = drawing::match_mouse_pos_waypoint(
                         mouse_pos_x,
                         mouse_pos_y,
-                        &positions(&gates),
-                        64,
-                        64,
+                        &circuit,
+                        &camera,
                     ) {
-                        let cables_to_remove: Vec<usize> = cables
-                            .iter()
-                            .enumerate()
-                            .filter(|(_, x)| {
-                                gates[element].input_positions().contains(&x.end_point)
-                                    || gates[element].output_positions().contains(&x.start_point)
-                            })
-                            .map(|(index, _)| index)
-                            .collect();
-
-                        for cable in cables_to_remove.iter().rev() {
-                            cables.remove(*cable);
+                        let from = circuit
+                            .cable(id)
+                            .map_or(Vec::new(), |x| x.waypoints.clone());
+                        let mut to = from.clone();
+                        to.remove(index);
+                        history.execute(Command::SetWaypoints { id, from, to }, &mut circuit);
+                        bend = None;
+                    } else if let Some(element) =
+                        drawing::match_mouse_pos(mouse_pos_x, mouse_pos_y, &circuit.gates, &camera)
+                    {
+                        let gate = circuit.gates[element].clone();
+                        let cables = circuit.cables_of(gate.id);
+                        history.execute(Command::RemoveGate(gate, cables), &mut circuit);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Delete),
+                    keymod,
+                    ..
+                } if prompt.is_none()
+                    && !circuit.gates.is_empty()
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    prompt = Some((Prompt::ConfirmClear, String::new()));
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Delete),
+                    ..
+                } if prompt.is_none() && !selection.is_empty() => {
+                    history.execute(selection.delete(&circuit), &mut circuit);
+                    selection.clear();
+                    drag = None;
+                    bend = None;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::D),
+                    keymod,
+                    ..
+                } if prompt.is_none()
+                    && !selection.gates.is_empty()
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    let offset = Position::new(DUPLICATE_OFFSET, DUPLICATE_OFFSET);
+                    history.push(selection.duplicate(&mut circuit, offset));
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::C | Keycode::X)),
+                    keymod,
+                    ..
+                } if prompt.is_none()
+                    && !selection.gates.is_empty()
+                    && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) =>
+                {
+                    // the clipboard holds the gates in the file format, with what they use
+                    let fragment = circuit.extract(&selection.gates);
+                    let text = storage::serialize(&fragment, &[]);
+                    if let Err(e) = video_subsystem.clipboard().set_clipboard_text(&text) {
+                        eprintln!("could not copy: {}", e);
+                    } else if keycode == Keycode::X {
+                        history.execute(selection.delete(&circuit), &mut circuit);
+                        selection.clear();
+                        drag = None;
+                        bend = None;
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::V),
+                    keymod,
+                    ..
+                } if prompt.is_none() && keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let pasted = video_subsystem
+                        .clipboard()
+                        .clipboard_text()
+                        .and_then(|x| storage::deserialize(&x));
+                    // pasted custom gates share the definitions of the same name, which
+                    // have to match them
+                    let known = storage::collect_definitions(&circuit, &definitions(&palette));
+                    let pasted = pasted.and_then(|(mut fragment, fragment_definitions)| {
+                        storage::adopt_definitions(&mut fragment, fragment_definitions, &known)
+                            .map(|x| (fragment, x))
+                    });
+                    match pasted {
+                        Ok((fragment, fragment_definitions)) => {
+                            for definition in fragment_definitions {
+                                if !palette_has_room(palette.len(), width, height) {
+                                    eprintln!("no room in the palette for {}", definition.name);
+                                    continue;
+                                }
+                                palette.push(custom_menuitem(definition));
+                            }
+                            layout_palette(&mut palette, width, height);
+                            let mut at = camera.to_world(mouse_pos_x, mouse_pos_y);
+                            if grid_shown {
+                                at = at.snap(grid_pitch as i32);
+                            }
+                            let command;
+                            (selection, command) = Selection::paste(&mut circuit, &fragment, at);
+                            history.push(command);
+                            drag = None;
+                            bend = None;
                         }
-
-                        inputs.remove(&gates[element].position);
-                        outputs.remove(&gates[element].position);
-                        gates.remove(element);
+                        Err(e) => eprintln!("could not paste: {}", e),
                     }
                 }
-                Event::MouseButtonUp {
-                    mouse_btn: sdl2::mouse::MouseButton::Middle,
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::H | Keycode::V)),
                     ..
-                } => {
-                    gates.clear();
-                    cables.clear();
-                    inputs.clear();
-                    outputs.clear();
+                } if prompt.is_none() && selection.gates.len() > 1 => {
+                    let axis = if keycode == Keycode::H {
+                        Axis::Row
+                    } else {
+                        Axis::Column
+                    };
+                    history.execute(selection.align(&circuit, axis), &mut circuit);
                 }
                 Event::KeyDown {
                     keycode: Some(Keycode::S),
                     ..
-                } => {
-                    if let Some(element) = drawing::match_mouse_pos(
-                        mouse_pos_x,
-                        mouse_pos_y,
-                        &positions(&gates),
-                        64,
-                        64,
-                    ) {
-                        if gates[element].gatetype == GateType::Switch {
-                            if gates[element].output_is_on()[0] {
-                                gates[element].input_values = Some(0);
-                                gates[element].texture = &and_placeholder;
+                } if prompt.is_none() => {
+                    if let Some(element) =
+                        drawing::match_mouse_pos(mouse_pos_x, mouse_pos_y, &circuit.gates, &camera)
+                    {
+                        let gate = &circuit.gates[element];
+                        if gate.gatetype == GateType::Switch {
+                            let command = Command::SetSwitch {
+                                id: gate.id,
+                                on: !gate.switch_is_on(),
+                            };
+                            history.execute(command, &mut circuit);
+                        }
+                    }
+                }
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            keycode @ (Keycode::Plus
+                            | Keycode::Equals
+                            | Keycode::KpPlus
+                            | Keycode::Minus
+                            | Keycode::KpMinus),
+                        ),
+                    ..
+                } if prompt.is_none() => {
+                    if let Some(element) =
+                        drawing::match_mouse_pos(mouse_pos_x, mouse_pos_y, &circuit.gates, &camera)
+                    {
+                        let gate = &mut circuit.gates[element];
+                        if let Some(period) = gate.behavior.period() {
+                            let faster = !matches!(keycode, Keycode::Minus | Keycode::KpMinus);
+                            let period = if faster {
+                                period / 2
                             } else {
-                                gates[element].input_values = Some(1);
-                                gates[element].texture = &switch_texture;
-                            }
+                                period.saturating_mul(2).min(MAX_CLOCK_PERIOD)
+                            };
+                            gate.behavior.set_period(period);
+                            gate.gatename = clock_label(gate.behavior.period().unwrap_or(period));
                         }
                     }
                 }
-                _ => {}
-            }
-        }
-
-        if moved_new {
-            let end = positions(&gates).len() - 1;
-
-            inputs.remove(&gates[end].position);
-            outputs.remove(&gates[end].position);
-
-            gates[end].position = Point::new(mouse_pos_x, mouse_pos_y);
-
-            inputs.insert(gates[end].position, gates[end].input_positions());
-            outputs.insert(gates[end].position, gates[end].output_positions());
-        }
-
-        let mut indices_start: Vec<(usize, usize, usize)> = Vec::new();
-        let mut indices_end: Vec<(usize, usize, usize)> = Vec::new();
-
-        for (cable_index, cable) in cables.iter().enumerate() {
-            for (gate_index, gate) in gates.iter().enumerate() {
-                for (input_index, input_positions) in gate.input_positions().iter().enumerate() {
-                    if input_positions.x() == cable.end_point.x()
-                        && input_positions.y() == cable.end_point.y()
+                Event::KeyDown {
+                    keycode: Some(Keycode::W),
+                    ..
+                } if prompt.is_none() => {
+                    if let Some(element) =
+                        drawing::match_mouse_pos(mouse_pos_x, mouse_pos_y, &circuit.gates, &camera)
                     {
-                        indices_end.push((cable_index, gate_index, input_index));
+                        let gate = &circuit.gates[element];
+                        if let Some(bits) = gate.behavior.width() {
+                            prompt = Some((Prompt::Width(gate.id), bits.to_string()));
+                            video_subsystem.text_input().start();
+                        }
                     }
                 }
-
-                for (output_index, output_positions) in gate.output_positions().iter().enumerate() {
-                    if output_positions.x() == cable.start_point.x()
-                        && output_positions.y() == cable.start_point.y()
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    ..
+                } if prompt.is_none() => {
+                    if let Some(element) =
+                        drawing::match_mouse_pos(mouse_pos_x, mouse_pos_y, &circuit.gates, &camera)
                     {
-                        indices_start.push((cable_index, gate_index, output_index));
+                        let gate = &circuit.gates[element];
+                        if let Some(period) = gate.behavior.period() {
+                            prompt = Some((Prompt::ClockRate(gate.id), period.to_string()));
+                            video_subsystem.text_input().start();
+                        }
                     }
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } if prompt.is_none() => {
+                    paused = !paused;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    ..
+                } if prompt.is_none() && paused => {
+                    single_step = true;
+                }
+                _ => {}
             }
         }
 
-        if moved_old {
-            inputs.remove(&gates[moved_old_index].position);
-            outputs.remove(&gates[moved_old_index].position);
-
-            gates[moved_old_index].position = Point::new(mouse_pos_x, mouse_pos_y);
-
-            inputs.insert(
-                gates[moved_old_index].position,
-                gates[moved_old_index].input_positions(),
-            );
-            outputs.insert(
-                gates[moved_old_index].position,
-                gates[moved_old_index].output_positions(),
-            );
+        if let Some(last) = pan.as_mut() {
+            camera.pan(mouse_pos_x - last.x, mouse_pos_y - last.y);
+            *last = Position::new(mouse_pos_x, mouse_pos_y);
         }
-
-        let old_cables = cables.clone();
-
-        for (cable, gate, index) in indices_start {
-            cables[cable].state = if gates[gate].output_is_on()[index] {
-                State::On
-            } else {
-                State::Off
+        if let Some(drag) = drag.as_ref() {
+            let mouse = camera.to_world(mouse_pos_x, mouse_pos_y);
+            let (dx, dy) = (mouse.x - drag.origin.x, mouse.y - drag.origin.y);
+            // a click without moving the mouse leaves gates off the grid where they are
+            let snap = grid_shown && (drag.placing || (dx, dy) != (0, 0));
+            let shift = |from: &Position| {
+                let to = Position::new(from.x + dx, from.y + dy);
+                if snap {
+                    to.snap(grid_pitch as i32)
+                } else {
+                    to
+                }
             };
-
-            if gate == moved_old_index {
-                cables[cable].start_point = gates[moved_old_index].output_positions()[index];
+            for (id, from) in drag.gates.iter() {
+                circuit.move_gate(*id, shift(from));
             }
-        }
-
-        for (cable, gate, index) in indices_end {
-            if gate == moved_old_index {
-                cables[cable].end_point = gates[moved_old_index].input_positions()[index];
+            for (id, from) in drag.cables.iter() {
+                circuit.set_waypoints(*id, from.iter().map(shift).collect());
             }
-
-            if cables[cable].state != old_cables[cable].state && gates[gate].input_values.is_some()
+        }
+        if let Some(bend) = bend.as_ref() {
+            let mouse = camera.to_world(mouse_pos_x, mouse_pos_y);
+            let point = if grid_shown {
+                mouse.snap(grid_pitch as i32)
+            } else {
+                mouse
+            };
+            if let Some(waypoint) = circuit
+                .cable_mut(bend.cable)
+                .and_then(|x| x.waypoints.get_mut(bend.index))
             {
-                gates[gate].input_values =
-                    Some(gates[gate].input_values.unwrap() ^ 2u64.pow(index as u32));
-            }
-
-            if gates[gate].gatetype == GateType::Lamp {
-                if gates[gate].output_is_on()[0] {
-                    gates[gate].texture = &switch_texture;
-                } else {
-                    gates[gate].texture = &and_placeholder;
-                }
+                *waypoint = point;
             }
         }
+        // gates and cables removed by undo or a right click drop out of the selection
+        selection.retain_existing(&circuit);
 
-        input_points.clear();
-        for v in inputs.values() {
-            for point in v {
-                input_points.push(*point);
-            }
+        // routes only change with the layout; working them out every frame is too slow
+        let layout = Layout::of(&circuit);
+        if layout != routed {
+            let stale = routed.stale(&layout, &circuit);
+            circuit.route_cables(&stale);
+            routed = layout;
         }
 
-        output_points.clear();
-        for v in outputs.values() {
-            for point in v {
-                output_points.push(*point);
-            }
+        if !paused || single_step {
+            simulation::tick(&mut circuit);
+            single_step = false;
+        } else {
+            simulation::simulate(&mut circuit);
         }
 
         drawing::render(
             &mut canvas,
             &font,
-            &positions_menuitems,
-            &gatenames_menuitems,
-            &textures_menuitems,
-            &gates,
-            &cables,
-            &input_points,
-            &output_points,
+            &textures,
+            &palette,
+            &circuit,
+            prompt
+                .as_ref()
+                .map(|(kind, input)| (kind.label(), input.as_str())),
+            truth_table.as_ref().map(|x| (x, truth_table_scroll)),
+            paused,
+            &selection,
+            band.map(|(corner, _)| (corner, camera.to_world(mouse_pos_x, mouse_pos_y))),
+            &camera,
+            grid_shown.then_some(grid_pitch),
         )?;
 
+        // the recovery file is left alone until the user decides whether to restore it
+        if !matches!(prompt, Some((Prompt::Restore, _))) {
+            autosave_ticks += 1;
+        }
+        if autosave_ticks >= AUTOSAVE_SECONDS * simulation::TICKS_PER_SECOND {
+            autosave_ticks = 0;
+            let text = storage::serialize(&circuit, &definitions(&palette));
+            if text != autosaved_text {
+                if let Err(e) = fs::write(RECOVERY_PATH, &text) {
+                    eprintln!("could not autosave to {}: {}", RECOVERY_PATH, e);
+                }
+                autosaved_text = text;
+            }
+        }
+
         // Time management!
-        ::std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
+        ::std::thread::sleep(Duration::new(
+            0,
+            1_000_000_000u32 / simulation::TICKS_PER_SECOND,
+        ));
     }
 
     Ok(())
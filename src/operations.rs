@@ -29,20 +29,43 @@ pub fn nand_func(inputs: &[bool]) -> Vec<bool> {
     vec![!and_func(inputs)[SINGLE_OUTPUT]]
 }
 
+pub fn nor_func(inputs: &[bool]) -> Vec<bool> {
+    vec![!or_func(inputs)[SINGLE_OUTPUT]]
+}
+
+/// True if an odd number of inputs is set.
 pub fn xor_func(inputs: &[bool]) -> Vec<bool> {
-    if and_func(inputs)[SINGLE_OUTPUT] || inputs.iter().all(|&x| !x) {
-        return vec![false];
-    }
-    vec![true]
+    vec![inputs.iter().filter(|&&x| x).count() % 2 == 1]
+}
+
+pub fn xnor_func(inputs: &[bool]) -> Vec<bool> {
+    vec![!xor_func(inputs)[SINGLE_OUTPUT]]
 }
 
-// [TODO] remove this function and implement functionality for adding custom gates
-pub fn add_func(inputs: &[bool]) -> Vec<bool> {
-    vec![
-        xor_func(&[inputs[2], xor_func(&[inputs[0], inputs[1]])[0]])[0],
-        or_func(&[
-            and_func(&[inputs[0], inputs[1]])[0],
-            and_func(&[inputs[2], xor_func(&[inputs[0], inputs[1]])[0]])[0],
-        ])[0],
-    ]
+pub fn buffer_func(inputs: &[bool]) -> Vec<bool> {
+    vec![inputs[0]]
+}
+
+/// Odd and even parity of the inputs.
+pub fn parity_func(inputs: &[bool]) -> Vec<bool> {
+    let odd = xor_func(inputs)[SINGLE_OUTPUT];
+    vec![odd, !odd]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xor_is_odd_parity() {
+        for inputs in 2..=5 {
+            for pattern in 0u32..1 << inputs {
+                let bits: Vec<bool> = (0..inputs).map(|x| pattern >> x & 1 == 1).collect();
+                let odd = pattern.count_ones() % 2 == 1;
+                assert_eq!(xor_func(&bits), vec![odd]);
+                assert_eq!(xnor_func(&bits), vec![!odd]);
+                assert_eq!(parity_func(&bits), vec![odd, !odd]);
+            }
+        }
+    }
 }
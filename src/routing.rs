@@ -0,0 +1,451 @@
+use crate::cable::{Cable, CableId};
+use crate::circuit::{Circuit, Pin};
+use crate::gate::Position;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Straight run out of an output and into an input before a wire may bend.
+pub const STUB: i32 = 16;
+/// Room kept between a wire and the gates it passes.
+pub const CLEARANCE: i32 = 8;
+// a bend costs as much as this many pixels of extra wire
+const BEND_COST: i64 = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Direction {
+    Right,
+    Left,
+    Down,
+    Up,
+}
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Right,
+    Direction::Left,
+    Direction::Down,
+    Direction::Up,
+];
+
+/// A rectangle wires may run along but not through.
+#[derive(Clone, Copy, Debug)]
+struct Area {
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+}
+
+impl Area {
+    fn around(a: Position, b: Position) -> Self {
+        Self {
+            left: a.x.min(b.x),
+            top: a.y.min(b.y),
+            right: a.x.max(b.x),
+            bottom: a.y.max(b.y),
+        }
+    }
+
+    fn grow(self, by: i32) -> Self {
+        Self {
+            left: self.left - by,
+            top: self.top - by,
+            right: self.right + by,
+            bottom: self.bottom + by,
+        }
+    }
+
+    fn union(self, other: &Area) -> Self {
+        Self {
+            left: self.left.min(other.left),
+            top: self.top.min(other.top),
+            right: self.right.max(other.right),
+            bottom: self.bottom.max(other.bottom),
+        }
+    }
+
+    fn overlaps(&self, other: &Area) -> bool {
+        self.left < other.right
+            && other.left < self.right
+            && self.top < other.bottom
+            && other.top < self.bottom
+    }
+
+    fn contains(&self, point: Position) -> bool {
+        self.left < point.x && point.x < self.right && self.top < point.y && point.y < self.bottom
+    }
+
+    fn contains_or_touches(&self, point: Position) -> bool {
+        (self.left..=self.right).contains(&point.x) && (self.top..=self.bottom).contains(&point.y)
+    }
+
+    /// Whether the horizontal or vertical segment from `a` to `b` passes through the inside.
+    fn blocks(&self, a: Position, b: Position) -> bool {
+        self.overlaps(&Area::around(a, b))
+    }
+}
+
+/// Whether `path` runs through or along the body of a `width` by `height` gate centred on
+/// `position`, so that routing it again may find a different way.
+pub fn passes(path: &[Position], position: Position, width: u32, height: u32) -> bool {
+    let (w, h) = (width as i32 / 2, height as i32 / 2);
+    let area = Area::around(
+        Position::new(position.x - w, position.y - h),
+        Position::new(position.x + w, position.y + h),
+    )
+    .grow(CLEARANCE + 1);
+    path.windows(2).any(|x| area.blocks(x[0], x[1]))
+}
+
+/// The path of `cable` from its output to its input through its waypoints, bending only at
+/// right angles and going around gate bodies where it can. `None` if a pin is missing.
+pub fn route(circuit: &Circuit, cable: &Cable) -> Option<Vec<Position>> {
+    let start = circuit.output_position(cable.start)?;
+    let end = circuit.input_position(cable.end)?;
+    let obstacles: Vec<Area> = circuit
+        .gates
+        .iter()
+        .map(|gate| {
+            let (w, h) = (gate.width as i32 / 2, gate.height as i32 / 2);
+            Area::around(
+                Position::new(gate.position.x - w, gate.position.y - h),
+                Position::new(gate.position.x + w, gate.position.y + h),
+            )
+            .grow(CLEARANCE)
+        })
+        .collect();
+
+    let mut stops = vec![Position::new(start.x + STUB, start.y)];
+    stops.extend(cable.waypoints.iter().copied());
+    stops.push(Position::new(end.x - STUB, end.y));
+
+    let mut path = vec![start];
+    let mut heading = Direction::Right;
+    for (index, leg) in stops.windows(2).enumerate() {
+        // the last leg should arrive heading into the input
+        let arrival = (index + 2 == stops.len()).then_some(Direction::Right);
+        match route_leg(leg[0], leg[1], heading, arrival, &obstacles) {
+            Some((points, last)) => {
+                path.extend(points);
+                heading = last;
+            }
+            None => {
+                let middle = (leg[0].x + leg[1].x) / 2;
+                path.extend([
+                    leg[0],
+                    Position::new(middle, leg[0].y),
+                    Position::new(middle, leg[1].y),
+                    leg[1],
+                ]);
+                heading = Direction::Right;
+            }
+        }
+    }
+    path.push(end);
+    Some(simplify(path))
+}
+
+/// The cheapest orthogonal path between two points on the lines through the points and the
+/// edges of the gates around them, with each bend counted as `BEND_COST` pixels.
+fn route_leg(
+    from: Position,
+    to: Position,
+    heading: Direction,
+    arrival: Option<Direction>,
+    obstacles: &[Area],
+) -> Option<(Vec<Position>, Direction)> {
+    if from == to {
+        return Some((vec![from], heading));
+    }
+
+    // look at the box around both ends, grown to hold the gates in it and room to pass them
+    let around = Area::around(from, to).grow(STUB);
+    let area = obstacles
+        .iter()
+        .filter(|x| x.overlaps(&around))
+        .fold(around, |area, x| area.union(x))
+        .grow(STUB);
+    let nearby: Vec<Area> = obstacles
+        .iter()
+        .filter(|x| x.overlaps(&area))
+        .copied()
+        .collect();
+    if nearby.iter().any(|x| x.contains(from) || x.contains(to)) {
+        return None;
+    }
+
+    let mut xs = vec![from.x, to.x, area.left, area.right];
+    let mut ys = vec![from.y, to.y, area.top, area.bottom];
+    for obstacle in nearby.iter() {
+        xs.extend([obstacle.left, obstacle.right]);
+        ys.extend([obstacle.top, obstacle.bottom]);
+    }
+    for lines in [&mut xs, &mut ys] {
+        lines.sort_unstable();
+        lines.dedup();
+    }
+    xs.retain(|x| (area.left..=area.right).contains(x));
+    ys.retain(|y| (area.top..=area.bottom).contains(y));
+
+    let column = |x: i32| xs.binary_search(&x).ok();
+    let row = |y: i32| ys.binary_search(&y).ok();
+    let (start, target) = ((column(from.x)?, row(from.y)?), (column(to.x)?, row(to.y)?));
+    let state = |(x, y): (usize, usize), direction: Direction| {
+        (y * xs.len() + x) * DIRECTIONS.len() + direction as usize
+    };
+    let node = |state: usize| {
+        let cell = state / DIRECTIONS.len();
+        (
+            (cell % xs.len(), cell / xs.len()),
+            DIRECTIONS[state % DIRECTIONS.len()],
+        )
+    };
+
+    let mut costs = vec![i64::MAX; xs.len() * ys.len() * DIRECTIONS.len()];
+    let mut previous: Vec<Option<usize>> = vec![None; costs.len()];
+    let mut queue = BinaryHeap::new();
+    let mut best: Option<(i64, usize)> = None;
+    costs[state(start, heading)] = 0;
+    queue.push(Reverse((0, state(start, heading))));
+
+    while let Some(Reverse((cost, current))) = queue.pop() {
+        if cost > costs[current] {
+            continue;
+        }
+        if best.is_some_and(|(total, _)| cost >= total) {
+            break;
+        }
+        let ((x, y), direction) = node(current);
+        if (x, y) == target {
+            let total = cost
+                + if arrival.is_some_and(|x| x != direction) {
+                    BEND_COST
+                } else {
+                    0
+                };
+            if best.is_none_or(|(best, _)| total < best) {
+                best = Some((total, current));
+            }
+            continue;
+        }
+
+        let here = Position::new(xs[x], ys[y]);
+        for next_direction in DIRECTIONS {
+            let next = match next_direction {
+                Direction::Right if x + 1 < xs.len() => (x + 1, y),
+                Direction::Left if x > 0 => (x - 1, y),
+                Direction::Down if y + 1 < ys.len() => (x, y + 1),
+                Direction::Up if y > 0 => (x, y - 1),
+                _ => continue,
+            };
+            let there = Position::new(xs[next.0], ys[next.1]);
+            if nearby
+                .iter()
+                .any(|x| x.contains(there) || x.blocks(here, there))
+            {
+                continue;
+            }
+            let length = (here.x.abs_diff(there.x) + here.y.abs_diff(there.y)) as i64;
+            let bend = if next_direction == direction {
+                0
+            } else {
+                BEND_COST
+            };
+            let next_state = state(next, next_direction);
+            if cost + length + bend < costs[next_state] {
+                costs[next_state] = cost + length + bend;
+                previous[next_state] = Some(current);
+                queue.push(Reverse((costs[next_state], next_state)));
+            }
+        }
+    }
+
+    let (_, last) = best?;
+    let mut points = Vec::new();
+    let mut current = Some(last);
+    while let Some(state) = current {
+        let ((x, y), _) = node(state);
+        points.push(Position::new(xs[x], ys[y]));
+        current = previous[state];
+    }
+    points.reverse();
+    Some((points, node(last).1))
+}
+
+/// Drops repeated points and the middle one of three on a straight line, keeping the point
+/// a path turns back at, e.g. to reach a waypoint and return.
+fn simplify(path: Vec<Position>) -> Vec<Position> {
+    let mut points: Vec<Position> = Vec::with_capacity(path.len());
+    for point in path {
+        if points.last() == Some(&point) {
+            continue;
+        }
+        if let [.., a, b] = points[..] {
+            let straight = (a.x == b.x && b.x == point.x) || (a.y == b.y && b.y == point.y);
+            if straight && heading(a, b) == heading(b, point) {
+                points.pop();
+            }
+        }
+        points.push(point);
+    }
+    points
+}
+
+/// Points where the wires leaving one output split, found as points that wires of the same
+/// net leave in three or more directions. Each comes with one of the cables meeting there.
+pub fn junctions(circuit: &Circuit) -> Vec<(Position, CableId)> {
+    let mut junctions = Vec::new();
+    let mut outputs: Vec<Pin> = circuit.cables.iter().map(|x| x.start).collect();
+    outputs.sort_unstable_by_key(|x| (x.gate, x.index));
+    outputs.dedup();
+
+    for output in outputs {
+        let net: Vec<(CableId, Vec<Position>)> = circuit
+            .cables
+            .iter()
+            .filter(|x| x.start == output)
+            .filter_map(|x| Some((x.id, circuit.cable_path(x)?)))
+            .collect();
+        if net.len() < 2 {
+            continue;
+        }
+        let segments: Vec<(Position, Position)> = net
+            .iter()
+            .flat_map(|(_, path)| path.windows(2).map(|x| (x[0], x[1])))
+            .collect();
+
+        for (id, path) in net.iter() {
+            for point in path.iter().skip(1) {
+                let mut directions = [false; 4];
+                // straight paths of cables that were never routed may be diagonal
+                for &(a, b) in segments.iter().filter(|(a, b)| a.x == b.x || a.y == b.y) {
+                    if a == b || !Area::around(a, b).contains_or_touches(*point) {
+                        continue;
+                    }
+                    if *point != b {
+                        directions[heading(*point, b) as usize] = true;
+                    }
+                    if *point != a {
+                        directions[heading(*point, a) as usize] = true;
+                    }
+                }
+                if directions.iter().filter(|x| **x).count() >= 3
+                    && !junctions.iter().any(|(x, _)| x == point)
+                {
+                    junctions.push((*point, *id));
+                }
+            }
+        }
+    }
+    junctions
+}
+
+/// The direction from `from` towards `to` along a horizontal or vertical line.
+fn heading(from: Position, to: Position) -> Direction {
+    if to.x > from.x {
+        Direction::Right
+    } else if to.x < from.x {
+        Direction::Left
+    } else if to.y > from.y {
+        Direction::Down
+    } else {
+        Direction::Up
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::circuit::Pin;
+    use crate::gate::{GateId, GateType};
+    use crate::testing::add;
+
+    fn body(circuit: &Circuit, id: GateId) -> Area {
+        let gate = circuit.gate(id).expect("gate exists");
+        let (w, h) = (gate.width as i32 / 2, gate.height as i32 / 2);
+        Area::around(
+            Position::new(gate.position.x - w, gate.position.y - h),
+            Position::new(gate.position.x + w, gate.position.y + h),
+        )
+    }
+
+    fn route_all(circuit: &mut Circuit) {
+        let ids: Vec<CableId> = circuit.cables.iter().map(|x| x.id).collect();
+        circuit.route_cables(&ids);
+    }
+
+    #[test]
+    fn route_goes_around_gates_in_the_way() {
+        let mut circuit = Circuit::new();
+        let switch = add(&mut circuit, GateType::Switch, 100, 100);
+        let blocker = add(&mut circuit, GateType::Not, 300, 100);
+        let lamp = add(&mut circuit, GateType::Lamp, 500, 100);
+        let cable = circuit
+            .connect(Pin::new(switch, 0), Pin::new(lamp, 0))
+            .and_then(|x| circuit.cable(x))
+            .expect("cable fits")
+            .clone();
+
+        let path = route(&circuit, &cable).expect("pins exist");
+        assert_eq!(path.first().copied(), circuit.output_position(cable.start));
+        assert_eq!(path.last().copied(), circuit.input_position(cable.end));
+        let blocker = body(&circuit, blocker);
+        for segment in path.windows(2) {
+            assert!(segment[0].x == segment[1].x || segment[0].y == segment[1].y);
+            assert!(!blocker.blocks(segment[0], segment[1]));
+        }
+    }
+
+    #[test]
+    fn route_passes_through_waypoints() {
+        let mut circuit = Circuit::new();
+        let switch = add(&mut circuit, GateType::Switch, 100, 100);
+        let lamp = add(&mut circuit, GateType::Lamp, 500, 100);
+        let id = circuit
+            .connect(Pin::new(switch, 0), Pin::new(lamp, 0))
+            .expect("cable fits");
+        let waypoint = Position::new(300, 300);
+        circuit.set_waypoints(id, vec![waypoint]);
+
+        let cable = circuit.cable(id).expect("cable exists");
+        let path = route(&circuit, cable).expect("pins exist");
+        assert!(path
+            .windows(2)
+            .any(|x| Area::around(x[0], x[1]).contains_or_touches(waypoint)));
+    }
+
+    #[test]
+    fn junction_where_two_cables_of_one_output_split() {
+        let mut circuit = Circuit::new();
+        let switch = add(&mut circuit, GateType::Switch, 100, 200);
+        let upper = add(&mut circuit, GateType::Lamp, 500, 100);
+        let lower = add(&mut circuit, GateType::Lamp, 500, 300);
+        circuit.connect(Pin::new(switch, 0), Pin::new(upper, 0));
+        route_all(&mut circuit);
+        assert!(junctions(&circuit).is_empty());
+        circuit.connect(Pin::new(switch, 0), Pin::new(lower, 0));
+        route_all(&mut circuit);
+
+        let found = junctions(&circuit);
+        assert_eq!(found.len(), 1);
+        let (point, _) = found[0];
+        for cable in circuit.cables.iter() {
+            let path = circuit.cable_path(cable).expect("pins exist");
+            assert!(path
+                .windows(2)
+                .any(|x| Area::around(x[0], x[1]).contains_or_touches(point)));
+        }
+    }
+
+    #[test]
+    fn no_junction_where_cables_of_different_outputs_cross() {
+        let mut circuit = Circuit::new();
+        let first = add(&mut circuit, GateType::Switch, 100, 100);
+        let second = add(&mut circuit, GateType::Switch, 100, 300);
+        let upper = add(&mut circuit, GateType::Lamp, 500, 100);
+        let lower = add(&mut circuit, GateType::Lamp, 500, 300);
+        circuit.connect(Pin::new(first, 0), Pin::new(lower, 0));
+        circuit.connect(Pin::new(second, 0), Pin::new(upper, 0));
+        route_all(&mut circuit);
+        assert!(junctions(&circuit).is_empty());
+    }
+}
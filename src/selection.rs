@@ -0,0 +1,320 @@
+use crate::cable::CableId;
+use crate::circuit::Circuit;
+use crate::gate::{GateId, Position};
+use crate::history::Command;
+
+/// The common line `align` puts the selected gates on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    /// Same y as the topmost gate.
+    Row,
+    /// Same x as the leftmost gate.
+    Column,
+}
+
+/// Gates and cables picked for a group edit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Selection {
+    pub gates: Vec<GateId>,
+    pub cables: Vec<CableId>,
+}
+
+impl Selection {
+    /// The gates whose centre lies in the rectangle spanned by two corners, and the cables
+    /// running between them.
+    pub fn in_rect(circuit: &Circuit, corner: Position, other: Position) -> Self {
+        let (left, right) = (corner.x.min(other.x), corner.x.max(other.x));
+        let (top, bottom) = (corner.y.min(other.y), corner.y.max(other.y));
+        let gates: Vec<GateId> = circuit
+            .gates
+            .iter()
+            .filter(|x| (left..=right).contains(&x.position.x))
+            .filter(|x| (top..=bottom).contains(&x.position.y))
+            .map(|x| x.id)
+            .collect();
+        let cables = circuit
+            .cables
+            .iter()
+            .filter(|x| gates.contains(&x.start.gate) && gates.contains(&x.end.gate))
+            .map(|x| x.id)
+            .collect();
+        Self { gates, cables }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.gates.is_empty() && self.cables.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.gates.clear();
+        self.cables.clear();
+    }
+
+    /// Adds what `other` selects that is not selected yet.
+    pub fn extend(&mut self, other: Selection) {
+        for id in other.gates {
+            if !self.gates.contains(&id) {
+                self.gates.push(id);
+            }
+        }
+        for id in other.cables {
+            if !self.cables.contains(&id) {
+                self.cables.push(id);
+            }
+        }
+    }
+
+    pub fn toggle_gate(&mut self, id: GateId) {
+        match self.gates.iter().position(|x| *x == id) {
+            Some(index) => {
+                self.gates.remove(index);
+            }
+            None => self.gates.push(id),
+        }
+    }
+
+    pub fn toggle_cable(&mut self, id: CableId) {
+        match self.cables.iter().position(|x| *x == id) {
+            Some(index) => {
+                self.cables.remove(index);
+            }
+            None => self.cables.push(id),
+        }
+    }
+
+    /// Forgets gates and cables that are no longer in the circuit, e.g. after an undo.
+    pub fn retain_existing(&mut self, circuit: &Circuit) {
+        self.gates.retain(|x| circuit.gate(*x).is_some());
+        self.cables.retain(|x| circuit.cable(*x).is_some());
+    }
+
+    /// Removes the selected cables and gates, along with every cable attached to them.
+    pub fn delete(&self, circuit: &Circuit) -> Command {
+        let mut commands: Vec<Command> = circuit
+            .cables
+            .iter()
+            .filter(|x| {
+                self.cables.contains(&x.id)
+                    || self.gates.contains(&x.start.gate)
+                    || self.gates.contains(&x.end.gate)
+            })
+            .map(|x| Command::Disconnect(x.clone()))
+            .collect();
+        commands.extend(
+            circuit
+                .gates
+                .iter()
+                .filter(|x| self.gates.contains(&x.id))
+                .map(|x| Command::RemoveGate(x.clone(), Vec::new())),
+        );
+        Command::Batch(commands)
+    }
+
+    /// Lines the selected gates up in a row or a column.
+    pub fn align(&self, circuit: &Circuit, axis: Axis) -> Command {
+        let positions: Vec<(GateId, Position)> = self
+            .gates
+            .iter()
+            .filter_map(|x| circuit.gate(*x).map(|gate| (*x, gate.position)))
+            .collect();
+        let line = match axis {
+            Axis::Row => positions.iter().map(|x| x.1.y).min(),
+            Axis::Column => positions.iter().map(|x| x.1.x).min(),
+        }
+        .unwrap_or(0);
+
+        Command::Batch(
+            positions
+                .into_iter()
+                .map(|(id, from)| {
+                    let to = match axis {
+                        Axis::Row => Position::new(from.x, line),
+                        Axis::Column => Position::new(line, from.y),
+                    };
+                    Command::MoveGate { id, from, to }
+                })
+                .filter(|x| !matches!(x, Command::MoveGate { from, to, .. } if from == to))
+                .collect(),
+        )
+    }
+
+    /// Copies the selected gates with the cables between them, shifted by `offset`, selects
+    /// the copies and returns the already applied edit.
+    pub fn duplicate(&mut self, circuit: &mut Circuit, offset: Position) -> Command {
+        let fragment = circuit.extract(&self.gates);
+        let command;
+        (*self, command) = paste_shifted(circuit, &fragment, offset);
+        command
+    }
+
+    /// Adds a copy of `fragment` centred on `at`. Returns the copy as a selection and the
+    /// already applied edit.
+    pub fn paste(circuit: &mut Circuit, fragment: &Circuit, at: Position) -> (Self, Command) {
+        let xs = fragment.gates.iter().map(|x| x.position.x);
+        let ys = fragment.gates.iter().map(|x| x.position.y);
+        let center = match (xs.clone().min(), xs.max(), ys.clone().min(), ys.max()) {
+            (Some(left), Some(right), Some(top), Some(bottom)) => {
+                Position::new((left + right) / 2, (top + bottom) / 2)
+            }
+            _ => at,
+        };
+        paste_shifted(
+            circuit,
+            fragment,
+            Position::new(at.x - center.x, at.y - center.y),
+        )
+    }
+}
+
+fn paste_shifted(
+    circuit: &mut Circuit,
+    fragment: &Circuit,
+    offset: Position,
+) -> (Selection, Command) {
+    let (gates, cables) = circuit.paste(fragment, offset);
+    let mut commands: Vec<Command> = gates
+        .iter()
+        .filter_map(|x| circuit.gate(*x))
+        .map(|x| Command::AddGate(x.clone()))
+        .collect();
+    commands.extend(
+        cables
+            .iter()
+            .filter_map(|x| circuit.cable(*x))
+            .map(|x| Command::Connect(x.clone())),
+    );
+    (Selection { gates, cables }, Command::Batch(commands))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::GateType;
+    use crate::history::History;
+    use crate::testing::{add, wire};
+
+    /// A switch driving a NOT that drives a lamp, left to right.
+    fn chain() -> (Circuit, [GateId; 3]) {
+        let mut circuit = Circuit::new();
+        let switch = add(&mut circuit, GateType::Switch, 100, 100);
+        let not = add(&mut circuit, GateType::Not, 250, 100);
+        let lamp = add(&mut circuit, GateType::Lamp, 400, 100);
+        wire(&mut circuit, switch, 0, not, 0);
+        wire(&mut circuit, not, 0, lamp, 0);
+        (circuit, [switch, not, lamp])
+    }
+
+    #[test]
+    fn in_rect_takes_gates_by_centre_and_the_cables_between_them() {
+        let (circuit, [switch, not, _]) = chain();
+        let selection = Selection::in_rect(&circuit, Position::new(300, 0), Position::new(50, 150));
+        assert_eq!(selection.gates, vec![switch, not]);
+        assert_eq!(selection.cables.len(), 1);
+        let cable = circuit.cable(selection.cables[0]).expect("cable exists");
+        assert_eq!((cable.start.gate, cable.end.gate), (switch, not));
+    }
+
+    #[test]
+    fn undoing_delete_restores_gates_and_attached_cables() {
+        let (mut circuit, [_, not, _]) = chain();
+        let before = circuit.clone();
+        let mut history = History::default();
+        let selection = Selection {
+            gates: vec![not],
+            cables: Vec::new(),
+        };
+
+        history.execute(selection.delete(&circuit), &mut circuit);
+        assert_eq!(circuit.gates.len(), 2);
+        assert!(circuit.cables.is_empty());
+
+        history.undo(&mut circuit);
+        assert_eq!(circuit.gates.len(), 3);
+        assert_eq!(circuit.cables.len(), before.cables.len());
+        for cable in before.cables.iter() {
+            assert_eq!(circuit.cable(cable.id), Some(cable));
+        }
+    }
+
+    #[test]
+    fn duplicate_copies_inner_cables_and_selects_the_copies() {
+        let (mut circuit, [switch, not, lamp]) = chain();
+        let mut selection = Selection {
+            gates: vec![switch, not],
+            cables: Vec::new(),
+        };
+
+        let command = selection.duplicate(&mut circuit, Position::new(0, 200));
+        assert_eq!(circuit.gates.len(), 5);
+        assert_eq!(circuit.cables.len(), 3);
+        assert_eq!(selection.gates.len(), 2);
+        assert_eq!(selection.cables.len(), 1);
+        for id in selection.gates.iter() {
+            assert!(![switch, not, lamp].contains(id));
+            assert_eq!(circuit.gate(*id).expect("copy exists").position.y, 300);
+        }
+
+        command.revert(&mut circuit);
+        assert_eq!(circuit.gates.len(), 3);
+        assert_eq!(circuit.cables.len(), 2);
+    }
+
+    #[test]
+    fn align_moves_gates_onto_the_topmost_row() {
+        let mut circuit = Circuit::new();
+        let high = add(&mut circuit, GateType::And, 100, 50);
+        let low = add(&mut circuit, GateType::Or, 300, 200);
+        let selection = Selection {
+            gates: vec![high, low],
+            cables: Vec::new(),
+        };
+
+        selection.align(&circuit, Axis::Row).apply(&mut circuit);
+        assert_eq!(
+            circuit.gate(low).expect("gate exists").position,
+            Position::new(300, 50)
+        );
+        assert_eq!(
+            circuit.gate(high).expect("gate exists").position,
+            Position::new(100, 50)
+        );
+    }
+
+    #[test]
+    fn paste_centres_the_fragment_on_the_cursor() {
+        let (fragment, _) = chain();
+        let mut circuit = Circuit::new();
+        let (selection, _) = Selection::paste(&mut circuit, &fragment, Position::new(1000, 500));
+        let positions: Vec<Position> = selection
+            .gates
+            .iter()
+            .filter_map(|x| circuit.gate(*x))
+            .map(|x| x.position)
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                Position::new(850, 500),
+                Position::new(1000, 500),
+                Position::new(1150, 500)
+            ]
+        );
+        assert_eq!(selection.cables.len(), 2);
+    }
+
+    #[test]
+    fn paste_drops_cables_to_gates_outside_the_fragment() {
+        let (mut fragment, [_, _, lamp]) = chain();
+        fragment.gates.retain(|x| x.id != lamp);
+        let mut circuit = Circuit::new();
+
+        let (selection, command) =
+            Selection::paste(&mut circuit, &fragment, Position::new(500, 500));
+        assert_eq!(selection.gates.len(), 2);
+        assert_eq!(circuit.cables.len(), 1);
+
+        command.revert(&mut circuit);
+        assert!(circuit.gates.is_empty());
+        assert!(circuit.cables.is_empty());
+    }
+}
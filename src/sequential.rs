@@ -0,0 +1,204 @@
+use crate::behavior::GateBehavior;
+use crate::gate::GateType;
+
+/// Bits a new register stores.
+pub const DEFAULT_REGISTER_WIDTH: usize = 4;
+/// Most bits a register can be set to store, one input and one output pin each.
+pub const MAX_REGISTER_WIDTH: usize = 8;
+/// Inputs a register has after its data inputs: LOAD and CLK.
+pub const REGISTER_CONTROLS: usize = 2;
+
+/// Latches and flip-flops. Outputs are `Q` and `not Q`; the clock is always the last input
+/// and edge-triggered parts react to its rising edge only.
+#[derive(Clone, Copy)]
+pub struct FlipFlop {
+    gatetype: GateType,
+    q: bool,
+    last_clock: bool,
+}
+
+impl FlipFlop {
+    /// `gatetype` is one of `SrLatch` (S, R), `DLatch` (D, E), `DFlipFlop` (D, CLK),
+    /// `JkFlipFlop` (J, K, CLK) or `TFlipFlop` (T, CLK).
+    pub fn new(gatetype: GateType) -> Option<Self> {
+        match gatetype {
+            GateType::SrLatch
+            | GateType::DLatch
+            | GateType::DFlipFlop
+            | GateType::JkFlipFlop
+            | GateType::TFlipFlop => Some(Self {
+                gatetype,
+                q: false,
+                last_clock: false,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl GateBehavior for FlipFlop {
+    fn inputs(&self) -> usize {
+        match self.gatetype {
+            GateType::JkFlipFlop => 3,
+            _ => 2,
+        }
+    }
+
+    fn outputs(&self) -> usize {
+        2
+    }
+
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool> {
+        let clock = inputs.last().copied().unwrap_or(false);
+        let rising_edge = clock && !self.last_clock;
+        self.last_clock = clock;
+
+        self.q = match (self.gatetype, inputs) {
+            // reset wins if both are set
+            (GateType::SrLatch, [s, r]) => (self.q || *s) && !r,
+            (GateType::DLatch, [d, enable]) if *enable => *d,
+            (GateType::DFlipFlop, [d, _]) if rising_edge => *d,
+            (GateType::JkFlipFlop, [j, k, _]) if rising_edge => match (j, k) {
+                (true, true) => !self.q,
+                (true, false) => true,
+                (false, true) => false,
+                (false, false) => self.q,
+            },
+            (GateType::TFlipFlop, [t, _]) if rising_edge => self.q ^ t,
+            _ => self.q,
+        };
+
+        vec![self.q, !self.q]
+    }
+
+    fn state(&self) -> Option<Vec<bool>> {
+        Some(vec![self.q])
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(*self)
+    }
+}
+
+/// An N-bit register. Inputs are D0..Dn-1, LOAD and CLK; on a rising clock edge with LOAD
+/// set it stores D0..Dn-1, which it always drives on its outputs.
+#[derive(Clone)]
+pub struct Register {
+    bits: Vec<bool>,
+    last_clock: bool,
+}
+
+impl Register {
+    pub fn new(width: usize) -> Self {
+        Self {
+            bits: vec![false; width.clamp(1, MAX_REGISTER_WIDTH)],
+            last_clock: false,
+        }
+    }
+}
+
+impl GateBehavior for Register {
+    fn inputs(&self) -> usize {
+        self.bits.len() + REGISTER_CONTROLS
+    }
+
+    fn outputs(&self) -> usize {
+        self.bits.len()
+    }
+
+    fn evaluate(&mut self, inputs: &[bool]) -> Vec<bool> {
+        let width = self.bits.len();
+        let load = inputs.get(width).copied().unwrap_or(false);
+        let clock = inputs.get(width + 1).copied().unwrap_or(false);
+
+        if clock && !self.last_clock && load && inputs.len() >= width {
+            self.bits.copy_from_slice(&inputs[..width]);
+        }
+        self.last_clock = clock;
+
+        self.bits.clone()
+    }
+
+    fn width(&self) -> Option<u8> {
+        Some(self.bits.len() as u8)
+    }
+
+    /// Keeps the stored bits that remain; new ones start cleared.
+    fn set_width(&mut self, bits: u8) {
+        self.bits
+            .resize((bits as usize).clamp(1, MAX_REGISTER_WIDTH), false);
+    }
+
+    fn state(&self) -> Option<Vec<bool>> {
+        Some(self.bits.clone())
+    }
+
+    fn box_clone(&self) -> Box<dyn GateBehavior> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signal::Signal;
+
+    #[test]
+    fn d_flip_flop_triggers_on_the_rising_edge_only() {
+        let mut flip_flop = FlipFlop::new(GateType::DFlipFlop).expect("flip-flop type");
+        // D, CLK
+        assert_eq!(flip_flop.evaluate(&[true, false]), vec![false, true]);
+        assert_eq!(flip_flop.evaluate(&[true, true]), vec![true, false]);
+        assert_eq!(flip_flop.evaluate(&[false, true]), vec![true, false]);
+        assert_eq!(flip_flop.evaluate(&[false, false]), vec![true, false]);
+        assert_eq!(flip_flop.evaluate(&[false, true]), vec![false, true]);
+    }
+
+    #[test]
+    fn t_flip_flop_toggles_once_per_edge() {
+        let mut flip_flop = FlipFlop::new(GateType::TFlipFlop).expect("flip-flop type");
+        let mut q = Vec::new();
+        for clock in [true, true, false, true, false, false, true] {
+            q.push(flip_flop.evaluate(&[true, clock])[0]);
+        }
+        assert_eq!(q, vec![true, true, true, false, false, false, true]);
+    }
+
+    #[test]
+    fn unknown_inputs_read_as_low() {
+        let mut flip_flop = FlipFlop::new(GateType::DFlipFlop).expect("flip-flop type");
+        let high = Signal::from(true);
+        assert_eq!(
+            flip_flop.evaluate_signals(&[high, high]),
+            vec![high, Signal::from(false)]
+        );
+        // an X clock is a low clock, so the next high one is a rising edge
+        flip_flop.evaluate_signals(&[Signal::unknown(1), Signal::unknown(1)]);
+        assert_eq!(
+            flip_flop.evaluate_signals(&[Signal::floating(1), high]),
+            vec![Signal::from(false), high]
+        );
+    }
+
+    #[test]
+    fn register_loads_only_with_load_set() {
+        let mut register = Register::new(2);
+        // D0, D1, LOAD, CLK
+        assert_eq!(
+            register.evaluate(&[true, false, false, true]),
+            vec![false, false]
+        );
+        assert_eq!(
+            register.evaluate(&[true, false, true, false]),
+            vec![false, false]
+        );
+        assert_eq!(
+            register.evaluate(&[true, false, true, true]),
+            vec![true, false]
+        );
+        assert_eq!(
+            register.evaluate(&[false, true, true, true]),
+            vec![true, false]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::bus::mask;
+
+/// The level of a single bit in four-valued logic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    Low,
+    High,
+    /// High impedance: nothing drives the bit.
+    Z,
+    /// Unknown, e.g. two outputs driving different values onto one bus.
+    X,
+}
+
+/// A value on a pin or cable in four-valued logic, bit 0 being the lowest bit of a bus.
+///
+/// Bits set in `z` are undriven, bits set in `x` unknown; `value` only holds the known bits.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Signal {
+    pub value: u64,
+    pub z: u64,
+    pub x: u64,
+}
+
+impl From<u64> for Signal {
+    fn from(value: u64) -> Self {
+        Self { value, z: 0, x: 0 }
+    }
+}
+
+impl From<bool> for Signal {
+    fn from(value: bool) -> Self {
+        Self::from(value as u64)
+    }
+}
+
+impl Signal {
+    /// `width` undriven bits, the output of a disabled tri-state buffer.
+    pub fn floating(width: u8) -> Self {
+        Self {
+            value: 0,
+            z: mask(width),
+            x: 0,
+        }
+    }
+
+    /// `width` unknown bits.
+    pub fn unknown(width: u8) -> Self {
+        Self {
+            value: 0,
+            z: 0,
+            x: mask(width),
+        }
+    }
+
+    /// Whether every bit is a plain 0 or 1.
+    pub fn is_known(&self) -> bool {
+        self.z | self.x == 0
+    }
+
+    pub fn level(&self, bit: u8) -> Level {
+        if self.x >> bit & 1 == 1 {
+            Level::X
+        } else if self.z >> bit & 1 == 1 {
+            Level::Z
+        } else if self.value >> bit & 1 == 1 {
+            Level::High
+        } else {
+            Level::Low
+        }
+    }
+
+    /// Bit `bit` of this signal moved to bit `to` of an otherwise empty signal.
+    pub fn bit(&self, bit: u8, to: u8) -> Self {
+        Self {
+            value: (self.value >> bit & 1) << to,
+            z: (self.z >> bit & 1) << to,
+            x: (self.x >> bit & 1) << to,
+        }
+    }
+
+    /// The value of a net driven by both signals: undriven bits take the other side, bits
+    /// driven from both sides become unknown unless the two agree.
+    pub fn resolve(self, other: Signal) -> Signal {
+        let (driven, other_driven) = (!self.z, !other.z);
+        let conflict = driven & other_driven & (self.value ^ other.value);
+        let x = (self.x & driven) | (other.x & other_driven) | conflict;
+        let z = self.z & other.z;
+        Signal {
+            value: ((self.value & driven) | (other.value & other_driven)) & !x & !z,
+            z,
+            x,
+        }
+    }
+
+    /// Hex digits for the lowest `width` bits, with `Z` or `X` for digits that are not known.
+    pub fn to_hex(&self, width: u8) -> String {
+        (0..width.div_ceil(4))
+            .rev()
+            .map(|digit| {
+                let digit_mask = mask((width - digit * 4).min(4)) << (digit * 4);
+                if self.x & digit_mask != 0 {
+                    'X'
+                } else if self.z & digit_mask != 0 {
+                    'Z'
+                } else {
+                    char::from_digit((self.value >> (digit * 4) & 0xf) as u32, 16)
+                        .unwrap_or('?')
+                        .to_ascii_uppercase()
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_flags_contention() {
+        let net = Signal::from(0b01).resolve(Signal::from(0b11));
+        assert_eq!(net.level(0), Level::High);
+        assert_eq!(net.level(1), Level::X);
+        assert!(!net.is_known());
+    }
+
+    #[test]
+    fn resolve_lets_the_driven_side_through() {
+        assert_eq!(
+            Signal::floating(4).resolve(Signal::from(0b1010)),
+            Signal::from(0b1010)
+        );
+        assert_eq!(
+            Signal::from(true).resolve(Signal::from(true)),
+            Signal::from(true)
+        );
+        assert_eq!(
+            Signal::floating(4).resolve(Signal::floating(4)),
+            Signal::floating(4)
+        );
+    }
+}
@@ -0,0 +1,215 @@
+use crate::circuit::Circuit;
+use crate::gate::GateType;
+use crate::signal::Signal;
+use std::collections::VecDeque;
+
+/// How many ticks the front end runs per second.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// Upper bound of evaluations per gate before a feedback loop counts as oscillating.
+const MAX_EVENTS_PER_GATE: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Every gate output is stable.
+    Settled,
+    /// A feedback loop kept changing and was cut off after `MAX_EVENTS_PER_GATE` rounds.
+    Oscillating,
+}
+
+/// Computes every gate output from the switch states in one step.
+///
+/// Gates outside of feedback loops are evaluated once in topological order. Whatever is
+/// left (loops and everything they drive) is settled with an event queue that starts from
+/// the previous outputs, so latches built from raw gates keep their state.
+pub fn simulate(circuit: &mut Circuit) -> Outcome {
+    let gate_count = circuit.gates.len();
+
+    // drivers[gate][input] = every (gate, output) feeding that input
+    let mut drivers: Vec<Vec<Vec<(usize, usize)>>> = circuit
+        .gates
+        .iter()
+        .map(|x| vec![Vec::new(); x.inputs()])
+        .collect();
+    let mut fanout: Vec<Vec<usize>> = vec![Vec::new(); gate_count];
+
+    for cable in circuit.cables.iter() {
+        let (Some(start), Some(end)) = (
+            circuit.index_of(cable.start.gate),
+            circuit.index_of(cable.end.gate),
+        ) else {
+            continue;
+        };
+        if cable.end.index < drivers[end].len() {
+            drivers[end][cable.end.index].push((start, cable.start.index));
+            fanout[start].push(end);
+        }
+    }
+
+    let mut outputs: Vec<Vec<Signal>> = circuit
+        .gates
+        .iter()
+        .map(|x| x.output_values().to_vec())
+        .collect();
+
+    // levelized pass (Kahn's algorithm)
+    let mut pending: Vec<usize> = drivers
+        .iter()
+        .map(|x| x.iter().map(|d| d.len()).sum())
+        .collect();
+    let mut ready: VecDeque<usize> = (0..gate_count).filter(|&x| pending[x] == 0).collect();
+    let mut evaluated = vec![false; gate_count];
+
+    while let Some(gate) = ready.pop_front() {
+        outputs[gate] = evaluate(circuit, &drivers, &outputs, gate);
+        evaluated[gate] = true;
+        for &next in fanout[gate].iter() {
+            pending[next] -= 1;
+            if pending[next] == 0 {
+                ready.push_back(next);
+            }
+        }
+    }
+
+    // event-driven pass for feedback loops
+    let mut outcome = Outcome::Settled;
+    let mut queue: VecDeque<usize> = (0..gate_count).filter(|&x| !evaluated[x]).collect();
+    let mut queued: Vec<bool> = evaluated.iter().map(|x| !x).collect();
+    let mut budget = queue.len() * MAX_EVENTS_PER_GATE;
+
+    while let Some(gate) = queue.pop_front() {
+        if budget == 0 {
+            outcome = Outcome::Oscillating;
+            break;
+        }
+        budget -= 1;
+        queued[gate] = false;
+
+        let new_outputs = evaluate(circuit, &drivers, &outputs, gate);
+        if new_outputs != outputs[gate] {
+            outputs[gate] = new_outputs;
+            for &next in fanout[gate].iter() {
+                if !queued[next] {
+                    queued[next] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+
+    for cable in circuit.cables.iter_mut() {
+        if let Some(end) = circuit.gates.iter().position(|x| x.id == cable.end.gate) {
+            if let Some(input) = drivers[end].get(cable.end.index) {
+                cable.set_signal(resolve(input, &outputs));
+            }
+        }
+    }
+
+    outcome
+}
+
+/// Advances every clock by one tick and recomputes the circuit.
+pub fn tick(circuit: &mut Circuit) -> Outcome {
+    for gate in circuit.gates.iter_mut() {
+        gate.behavior.tick();
+    }
+    simulate(circuit)
+}
+
+/// The value of a net: an input without cables reads 0, one driven by several outputs gets
+/// the four-valued combination of all of them.
+fn resolve(drivers: &[(usize, usize)], outputs: &[Vec<Signal>]) -> Signal {
+    drivers
+        .iter()
+        .map(|&(start, output)| outputs[start].get(output).copied().unwrap_or_default())
+        .reduce(Signal::resolve)
+        .unwrap_or_default()
+}
+
+/// Feeds the current driver outputs into a gate and returns its new outputs.
+fn evaluate(
+    circuit: &mut Circuit,
+    drivers: &[Vec<Vec<(usize, usize)>>],
+    outputs: &[Vec<Signal>],
+    gate: usize,
+) -> Vec<Signal> {
+    if circuit.gates[gate].gatetype != GateType::Switch {
+        let input_values = drivers[gate].iter().map(|x| resolve(x, outputs)).collect();
+        circuit.gates[gate].input_values = input_values;
+    }
+    circuit.gates[gate].evaluate()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::GateId;
+    use crate::testing::{add, lit, set_switch, wire};
+
+    #[test]
+    fn chain_settles_in_one_step_whatever_the_gate_order() {
+        let mut circuit = Circuit::new();
+        // added from the lamp back to the switch, so the levelized pass has to reorder them
+        let lamp = add(&mut circuit, GateType::Lamp, 500, 100);
+        let nots: Vec<GateId> = (0..3)
+            .map(|x| add(&mut circuit, GateType::Not, 400 - x * 100, 100))
+            .collect();
+        let switch = add(&mut circuit, GateType::Switch, 0, 100);
+        wire(&mut circuit, switch, 0, nots[2], 0);
+        wire(&mut circuit, nots[2], 0, nots[1], 0);
+        wire(&mut circuit, nots[1], 0, nots[0], 0);
+        wire(&mut circuit, nots[0], 0, lamp, 0);
+
+        assert_eq!(simulate(&mut circuit), Outcome::Settled);
+        assert!(lit(&circuit, lamp));
+        set_switch(&mut circuit, switch, true);
+        assert_eq!(simulate(&mut circuit), Outcome::Settled);
+        assert!(!lit(&circuit, lamp));
+    }
+
+    #[test]
+    fn nor_latch_keeps_its_state() {
+        let mut circuit = Circuit::new();
+        let set = add(&mut circuit, GateType::Switch, 0, 100);
+        let reset = add(&mut circuit, GateType::Switch, 0, 300);
+        let q = add(&mut circuit, GateType::Or, 200, 100);
+        let q_not = add(&mut circuit, GateType::Not, 300, 100);
+        let not_q = add(&mut circuit, GateType::Or, 200, 300);
+        let not_q_not = add(&mut circuit, GateType::Not, 300, 300);
+        let lamp = add(&mut circuit, GateType::Lamp, 500, 100);
+        // a NOR is an OR followed by a NOT
+        wire(&mut circuit, reset, 0, q, 0);
+        wire(&mut circuit, not_q_not, 0, q, 1);
+        wire(&mut circuit, q, 0, q_not, 0);
+        wire(&mut circuit, set, 0, not_q, 0);
+        wire(&mut circuit, q_not, 0, not_q, 1);
+        wire(&mut circuit, not_q, 0, not_q_not, 0);
+        wire(&mut circuit, q_not, 0, lamp, 0);
+
+        set_switch(&mut circuit, set, true);
+        assert_eq!(simulate(&mut circuit), Outcome::Settled);
+        assert!(lit(&circuit, lamp));
+        set_switch(&mut circuit, set, false);
+        assert_eq!(simulate(&mut circuit), Outcome::Settled);
+        assert!(lit(&circuit, lamp));
+        set_switch(&mut circuit, reset, true);
+        assert_eq!(simulate(&mut circuit), Outcome::Settled);
+        assert!(!lit(&circuit, lamp));
+        set_switch(&mut circuit, reset, false);
+        assert_eq!(simulate(&mut circuit), Outcome::Settled);
+        assert!(!lit(&circuit, lamp));
+    }
+
+    #[test]
+    fn ring_of_three_inverters_oscillates() {
+        let mut circuit = Circuit::new();
+        let nots: Vec<GateId> = (0..3)
+            .map(|x| add(&mut circuit, GateType::Not, x * 100, 100))
+            .collect();
+        for (index, &not) in nots.iter().enumerate() {
+            wire(&mut circuit, not, 0, nots[(index + 1) % nots.len()], 0);
+        }
+
+        assert_eq!(simulate(&mut circuit), Outcome::Oscillating);
+    }
+}
@@ -0,0 +1,520 @@
+use crate::behavior;
+use crate::circuit::{Circuit, Pin};
+use crate::custom::{Composite, CustomGate};
+use crate::gate::{Gate, GateId, GateType, Position};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::rc::Rc;
+
+pub const FORMAT_HEADER: &str = "logic.sim";
+pub const FORMAT_VERSION: u32 = 3;
+
+/// Writes a circuit and the custom gates it may use to `path`.
+pub fn save(path: &Path, circuit: &Circuit, definitions: &[Rc<CustomGate>]) -> Result<(), String> {
+    fs::write(path, serialize(circuit, definitions)).map_err(|e| e.to_string())
+}
+
+/// Reads a circuit and its custom gate definitions from `path`.
+pub fn load(path: &Path) -> Result<(Circuit, Vec<Rc<CustomGate>>), String> {
+    let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    deserialize(&text)
+}
+
+/// Renders the line based circuit format:
+///
+/// ```text
+/// logic.sim 3
+/// custom "HALF"
+///   inputs 0 1
+///   outputs 4 5
+///   gate 0 Switch 100 200 64 64 0 1 - on " "
+///   gate 1 Clock 100 300 64 64 0 1 - 30 "1Hz"
+///   cable 0 0 2 0
+/// end
+/// gate 0 Custom 400 300 64 64 2 2 - - "HALF"
+/// gate 1 Splitter 600 300 64 64 1 8 8 - " "
+/// gate 2 Lamp 800 200 64 64 1 0 - - " "
+/// cable 1 0 2 0 700 240 700 200
+/// ```
+///
+/// Every definition is written once, before anything that uses it. The field after the pin
+/// counts is the bus width of gates that have one; version 1 files do not have it. Cables
+/// may end with the x and y of their waypoints, which files before version 3 do not have.
+pub fn serialize(circuit: &Circuit, definitions: &[Rc<CustomGate>]) -> String {
+    let mut text = format!("{} {}\n", FORMAT_HEADER, FORMAT_VERSION);
+    for definition in collect_definitions(circuit, definitions).iter() {
+        let _ = writeln!(text, "custom {}", quote(&definition.name));
+        let _ = writeln!(text, "  inputs{}", join_ids(&definition.inputs));
+        let _ = writeln!(text, "  outputs{}", join_ids(&definition.outputs));
+        write_circuit(&mut text, &definition.circuit, "  ");
+        text.push_str("end\n");
+    }
+    write_circuit(&mut text, circuit, "");
+    text
+}
+
+/// Parses the format written by `serialize`.
+pub fn deserialize(text: &str) -> Result<(Circuit, Vec<Rc<CustomGate>>), String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+    let version: u32 = match lines.next().map(|(_, line)| tokenize(line)) {
+        Some(Ok(header)) if header.len() == 2 && header[0] == FORMAT_HEADER => header[1]
+            .parse()
+            .map_err(|_| format!("invalid version `{}`", header[1]))?,
+        _ => return Err(format!("missing `{}` header", FORMAT_HEADER)),
+    };
+    if version > FORMAT_VERSION {
+        return Err(format!("unsupported version {}", version));
+    }
+
+    let mut definitions: Vec<Rc<CustomGate>> = Vec::new();
+    let mut circuit = Circuit::new();
+    let mut current: Option<CustomGate> = None;
+
+    for (number, line) in lines {
+        let tokens = tokenize(line).map_err(|e| format!("line {}: {}", number, e))?;
+        let target = match current.as_mut() {
+            Some(definition) => &mut definition.circuit,
+            None => &mut circuit,
+        };
+        let result = match tokens[0].as_str() {
+            "gate" => parse_gate(&tokens[1..], version, &definitions).and_then(|gate| match target
+                .gate(gate.id)
+            {
+                Some(_) => Err(format!("duplicate gate id {}", gate.id)),
+                None => {
+                    target.insert_gate(gate);
+                    Ok(())
+                }
+            }),
+            "cable" => parse_cable(&tokens[1..]).and_then(|(ids, waypoints)| match ids[..] {
+                [start, output, end, input] => target
+                    .connect(Pin::new(start, output), Pin::new(end, input))
+                    .map(|id| target.set_waypoints(id, waypoints))
+                    .ok_or_else(|| {
+                        "cable does not connect an output to an input of its width".to_string()
+                    }),
+                _ => {
+                    Err("expected `cable <gate> <output> <gate> <input> [<x> <y>]...`".to_string())
+                }
+            }),
+            "custom" if current.is_none() && tokens.len() == 2 => {
+                current = Some(CustomGate {
+                    name: tokens[1].clone(),
+                    circuit: Circuit::new(),
+                    inputs: Vec::new(),
+                    outputs: Vec::new(),
+                });
+                Ok(())
+            }
+            "inputs" => match current.as_mut() {
+                Some(definition) => parse_ids(&tokens[1..]).map(|x| definition.inputs = x),
+                None => Err("`inputs` outside of a custom gate".to_string()),
+            },
+            "outputs" => match current.as_mut() {
+                Some(definition) => parse_ids(&tokens[1..]).map(|x| definition.outputs = x),
+                None => Err("`outputs` outside of a custom gate".to_string()),
+            },
+            "end" => match current.take() {
+                Some(definition) if definitions.iter().any(|x| x.name == definition.name) => Err(
+                    format!("custom gate {} is defined twice", quote(&definition.name)),
+                ),
+                Some(definition) => {
+                    definitions.push(Rc::new(definition));
+                    Ok(())
+                }
+                None => Err("`end` outside of a custom gate".to_string()),
+            },
+            _ => Err(format!("unexpected `{}`", tokens[0])),
+        };
+        result.map_err(|e| format!("line {}: {}", number, e))?;
+    }
+
+    if let Some(definition) = current {
+        return Err(format!("custom gate {} is missing `end`", definition.name));
+    }
+
+    Ok((circuit, definitions))
+}
+
+/// `definitions` and every definition they or the gates of `circuit` use, each once and
+/// after the definitions it uses itself. Definitions are told apart by name.
+pub fn collect_definitions(
+    circuit: &Circuit,
+    definitions: &[Rc<CustomGate>],
+) -> Vec<Rc<CustomGate>> {
+    let mut ordered: Vec<Rc<CustomGate>> = Vec::new();
+    for definition in definitions.iter() {
+        collect_definition(definition, &mut ordered);
+    }
+    for gate in circuit.gates.iter() {
+        if let Some(definition) = gate.behavior.definition() {
+            collect_definition(definition, &mut ordered);
+        }
+    }
+    ordered
+}
+
+/// Binds the custom gates of a loaded `circuit` to the `known` definitions of the same name
+/// and returns the loaded definitions that are new. Fails if a loaded definition differs
+/// from the known one with its name, since a file can only hold one of them.
+pub fn adopt_definitions(
+    circuit: &mut Circuit,
+    loaded: Vec<Rc<CustomGate>>,
+    known: &[Rc<CustomGate>],
+) -> Result<Vec<Rc<CustomGate>>, String> {
+    let mut new = Vec::new();
+    for definition in loaded {
+        match known.iter().find(|x| x.name == definition.name) {
+            Some(existing) if definition_text(existing) != definition_text(&definition) => {
+                return Err(format!(
+                    "custom gate {} differs from the one already defined",
+                    quote(&definition.name)
+                ));
+            }
+            Some(_) => {}
+            None => new.push(definition),
+        }
+    }
+
+    for gate in circuit.gates.iter_mut() {
+        let existing = gate
+            .behavior
+            .definition()
+            .and_then(|definition| known.iter().find(|x| x.name == definition.name))
+            .cloned();
+        if let Some(existing) = existing {
+            gate.behavior = Box::new(Composite::new(existing));
+        }
+    }
+    Ok(new)
+}
+
+/// A definition with the ones it uses, as `serialize` writes them.
+fn definition_text(definition: &Rc<CustomGate>) -> String {
+    serialize(&Circuit::new(), std::slice::from_ref(definition))
+}
+
+fn collect_definition(definition: &Rc<CustomGate>, ordered: &mut Vec<Rc<CustomGate>>) {
+    if ordered.iter().any(|x| x.name == definition.name) {
+        return;
+    }
+    for gate in definition.circuit.gates.iter() {
+        if let Some(nested) = gate.behavior.definition() {
+            collect_definition(nested, ordered);
+        }
+    }
+    ordered.push(definition.clone());
+}
+
+fn write_circuit(text: &mut String, circuit: &Circuit, indent: &str) {
+    for gate in circuit.gates.iter() {
+        let value = match (gate.gatetype, gate.switch_is_on(), gate.behavior.period()) {
+            (GateType::Switch, false, _) => "off".to_string(),
+            (GateType::Switch, true, _) => "on".to_string(),
+            (_, _, Some(period)) => period.to_string(),
+            _ => "-".to_string(),
+        };
+        let bits = match gate.behavior.width() {
+            Some(bits) => bits.to_string(),
+            None => "-".to_string(),
+        };
+        let _ = writeln!(
+            text,
+            "{}gate {} {} {} {} {} {} {} {} {} {} {}",
+            indent,
+            gate.id,
+            gate.gatetype,
+            gate.position.x,
+            gate.position.y,
+            gate.width,
+            gate.height,
+            gate.inputs(),
+            gate.outputs(),
+            bits,
+            value,
+            quote(&gate.gatename),
+        );
+    }
+    for cable in circuit.cables.iter() {
+        let waypoints: String = cable
+            .waypoints
+            .iter()
+            .map(|x| format!(" {} {}", x.x, x.y))
+            .collect();
+        let _ = writeln!(
+            text,
+            "{}cable {} {} {} {}{}",
+            indent, cable.start.gate, cable.start.index, cable.end.gate, cable.end.index, waypoints
+        );
+    }
+}
+
+fn parse_gate(
+    tokens: &[String],
+    version: u32,
+    definitions: &[Rc<CustomGate>],
+) -> Result<Gate, String> {
+    let mut tokens = tokens.to_vec();
+    if version < 2 && tokens.len() == 10 {
+        tokens.insert(8, "-".to_string());
+    }
+    let [id, gatetype, x, y, width, height, inputs, outputs, bits, value, name] = &tokens[..]
+    else {
+        return Err(
+            "expected `gate <id> <type> <x> <y> <width> <height> <inputs> <outputs> \
+             <bits> <value> <name>`"
+                .to_string(),
+        );
+    };
+    let gatetype: GateType = gatetype.parse()?;
+    let position = Position::new(parse_number(x)?, parse_number(y)?);
+    let (width, height) = (parse_number(width)?, parse_number(height)?);
+    let (inputs, outputs): (usize, usize) = (parse_number(inputs)?, parse_number(outputs)?);
+
+    let mut gate = if gatetype == GateType::Custom {
+        let definition = definitions
+            .iter()
+            .find(|x| x.name == *name)
+            .ok_or_else(|| format!("unknown custom gate {}", quote(name)))?;
+        Gate::custom(definition.clone(), position, width, height)
+    } else {
+        let mut behavior = behavior::for_type(gatetype, inputs)
+            .ok_or_else(|| format!("{} has no built-in behaviour", gatetype))?;
+        if bits != "-" {
+            behavior.set_width(parse_number(bits)?);
+        }
+        let switch_on = match value.as_str() {
+            "on" => true,
+            "off" | "-" => false,
+            period => {
+                behavior.set_period(parse_number(period)?);
+                false
+            }
+        };
+        let mut gate = Gate::new(gatetype, name, position, width, height, behavior);
+        if switch_on {
+            gate.set_switch(true);
+        }
+        gate
+    };
+    if gate.inputs() != inputs || gate.outputs() != outputs {
+        return Err(format!(
+            "{} {} has {} inputs and {} outputs",
+            gatetype,
+            quote(name),
+            gate.inputs(),
+            gate.outputs()
+        ));
+    }
+    gate.id = parse_number(id)?;
+    Ok(gate)
+}
+
+fn parse_number<T: std::str::FromStr>(token: &str) -> Result<T, String> {
+    token
+        .parse()
+        .map_err(|_| format!("invalid number `{}`", token))
+}
+
+fn parse_ids(tokens: &[String]) -> Result<Vec<GateId>, String> {
+    tokens.iter().map(|x| parse_number(x)).collect()
+}
+
+/// The four pin ids of a cable line and the waypoints after them.
+fn parse_cable(tokens: &[String]) -> Result<(Vec<GateId>, Vec<Position>), String> {
+    let (ids, rest) = tokens.split_at(tokens.len().min(4));
+    if rest.len() % 2 != 0 {
+        return Err("waypoint is missing its y".to_string());
+    }
+    let waypoints = rest
+        .chunks(2)
+        .map(|x| Ok(Position::new(parse_number(&x[0])?, parse_number(&x[1])?)))
+        .collect::<Result<_, String>>()?;
+    Ok((parse_ids(ids)?, waypoints))
+}
+
+fn join_ids(ids: &[GateId]) -> String {
+    ids.iter().map(|x| format!(" {}", x)).collect()
+}
+
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Splits a line at whitespace, keeping quoted strings together.
+fn tokenize(line: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let mut token = String::new();
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => token.extend(chars.next()),
+                    Some(c) => token.push(c),
+                    None => return Err("unterminated string".to_string()),
+                }
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::library;
+    use crate::testing::{add, set_switch, wire};
+
+    #[test]
+    fn round_trip_keeps_gates_cables_and_definitions() {
+        let mut inner = Circuit::new();
+        let a = add(&mut inner, GateType::Switch, 100, 100);
+        let b = add(&mut inner, GateType::Switch, 100, 200);
+        let and = add(&mut inner, GateType::And, 250, 150);
+        let out = add(&mut inner, GateType::Lamp, 400, 150);
+        wire(&mut inner, a, 0, and, 0);
+        wire(&mut inner, b, 0, and, 1);
+        wire(&mut inner, and, 0, out, 0);
+        let definition = Rc::new(CustomGate::from_circuit("AND2", &inner).expect("not recursive"));
+
+        let mut circuit = Circuit::new();
+        let switch = add(&mut circuit, GateType::Switch, 100, 100);
+        let lamp = add(&mut circuit, GateType::Lamp, 500, 100);
+        let custom = circuit.add_gate(Gate::custom(
+            definition.clone(),
+            Position::new(300, 100),
+            64,
+            64,
+        ));
+        set_switch(&mut circuit, switch, true);
+        wire(&mut circuit, switch, 0, custom, 0);
+        wire(&mut circuit, switch, 0, custom, 1);
+        wire(&mut circuit, custom, 0, lamp, 0);
+
+        let text = serialize(&circuit, &[definition]);
+        let (loaded, definitions) = deserialize(&text).expect("file loads");
+        assert_eq!(serialize(&loaded, &definitions), text);
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].name, "AND2");
+        assert_eq!(loaded.gates.len(), 3);
+        assert_eq!(loaded.cables.len(), 3);
+    }
+
+    #[test]
+    fn round_trip_keeps_nested_definitions() {
+        let full_adder = Rc::new(library::full_adder());
+        let mut circuit = Circuit::new();
+        circuit.add_gate(Gate::custom(
+            full_adder.clone(),
+            Position::new(300, 100),
+            64,
+            64,
+        ));
+
+        let text = serialize(&circuit, &[full_adder]);
+        let (loaded, definitions) = deserialize(&text).expect("file loads");
+        assert_eq!(serialize(&loaded, &definitions), text);
+        let names: Vec<&str> = definitions.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["HALF", "ADD"]);
+    }
+
+    #[test]
+    fn loaded_custom_gates_share_known_definitions_of_their_name() {
+        let known = collect_definitions(&Circuit::new(), &[Rc::new(library::full_adder())]);
+        let mut circuit = Circuit::new();
+        circuit.add_gate(Gate::custom(
+            Rc::new(library::full_adder()),
+            Position::new(300, 100),
+            64,
+            64,
+        ));
+        let text = serialize(&circuit, &[]);
+
+        let (mut loaded, definitions) = deserialize(&text).expect("file loads");
+        let new = adopt_definitions(&mut loaded, definitions, &known).expect("definitions match");
+        assert!(new.is_empty());
+        let definition = loaded.gates[0].behavior.definition().expect("custom gate");
+        assert!(Rc::ptr_eq(definition, &known[1]));
+        assert_eq!(serialize(&loaded, &known), text);
+    }
+
+    #[test]
+    fn rejects_definitions_that_differ_from_known_ones_of_their_name() {
+        let known = collect_definitions(&Circuit::new(), &[Rc::new(library::full_adder())]);
+        let mut inner = Circuit::new();
+        let a = add(&mut inner, GateType::Switch, 100, 100);
+        let b = add(&mut inner, GateType::Switch, 100, 200);
+        let and = add(&mut inner, GateType::And, 250, 150);
+        let out = add(&mut inner, GateType::Lamp, 400, 150);
+        wire(&mut inner, a, 0, and, 0);
+        wire(&mut inner, b, 0, and, 1);
+        wire(&mut inner, and, 0, out, 0);
+        let half = Rc::new(CustomGate::from_circuit("HALF", &inner).expect("not recursive"));
+        let mut circuit = Circuit::new();
+        circuit.add_gate(Gate::custom(half, Position::new(300, 100), 64, 64));
+
+        let (mut loaded, definitions) = deserialize(&serialize(&circuit, &[])).expect("file loads");
+        assert!(adopt_definitions(&mut loaded, definitions, &known).is_err());
+    }
+
+    #[test]
+    fn round_trip_keeps_waypoints() {
+        let mut circuit = Circuit::new();
+        let switch = add(&mut circuit, GateType::Switch, 100, 100);
+        let lamp = add(&mut circuit, GateType::Lamp, 500, 100);
+        let id = circuit
+            .connect(Pin::new(switch, 0), Pin::new(lamp, 0))
+            .expect("cable fits");
+        let waypoints = vec![Position::new(300, 40), Position::new(400, 40)];
+        circuit.set_waypoints(id, waypoints.clone());
+
+        let text = serialize(&circuit, &[]);
+        let (loaded, _) = deserialize(&text).expect("file loads");
+        assert_eq!(serialize(&loaded, &[]), text);
+        assert_eq!(loaded.cables[0].waypoints, waypoints);
+    }
+
+    #[test]
+    fn loads_version_1_files() {
+        let text = "logic.sim 1\n\
+                    gate 0 Switch 100 100 64 64 0 1 on \" \"\n\
+                    gate 1 And 300 100 64 64 2 1 - \"AND\"\n\
+                    cable 0 0 1 0\n";
+        let (circuit, definitions) = deserialize(text).expect("file loads");
+        assert!(definitions.is_empty());
+        assert_eq!(circuit.gates.len(), 2);
+        assert_eq!(circuit.gate(1).expect("gate loaded").gatename, "AND");
+        assert_eq!(circuit.cables.len(), 1);
+        assert!(circuit.cables[0].waypoints.is_empty());
+    }
+
+    #[test]
+    fn rejects_newer_versions() {
+        let text = format!("{} {}\n", FORMAT_HEADER, FORMAT_VERSION + 1);
+        assert!(deserialize(&text).is_err());
+    }
+}
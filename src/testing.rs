@@ -0,0 +1,26 @@
+//! Shortcuts for building small circuits in unit tests.
+
+use crate::behavior;
+use crate::circuit::Circuit;
+use crate::gate::{Gate, GateId, GateType, Position};
+
+pub(crate) use crate::library::{builtin as add, wire};
+
+/// A built-in gate of `gatetype` at the origin, not yet placed in a circuit.
+pub fn gate(gatetype: GateType) -> Gate {
+    let behavior = behavior::for_type(gatetype, 2).expect("built-in gate type");
+    Gate::new(gatetype, " ", Position::default(), 64, 64, behavior)
+}
+
+pub fn set_switch(circuit: &mut Circuit, switch: GateId, on: bool) {
+    circuit
+        .gate_mut(switch)
+        .expect("switch exists")
+        .set_switch(on);
+}
+
+/// Whether a lamp is lit after the last simulation step.
+pub fn lit(circuit: &Circuit, lamp: GateId) -> bool {
+    let lamp = circuit.gate(lamp).expect("lamp exists");
+    lamp.output_is_on().first().copied().unwrap_or(false)
+}
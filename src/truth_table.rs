@@ -0,0 +1,184 @@
+use crate::circuit::Circuit;
+use crate::custom::CustomGate;
+use crate::simulation;
+
+/// The most switches a truth table enumerates (2^16 rows).
+pub const MAX_INPUTS: usize = 16;
+
+/// Every combination of the switches of a circuit with the lamps it lights up.
+///
+/// Inputs and outputs follow the pin order of a custom gate made from the circuit. Rows count
+/// up in binary with the first input as the most significant bit.
+pub struct TruthTable {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub rows: Vec<(Vec<bool>, Vec<bool>)>,
+}
+
+impl TruthTable {
+    pub fn of_circuit(circuit: &Circuit) -> Result<Self, String> {
+        let definition = CustomGate::from_circuit("", circuit)?;
+        Self::of_custom_gate(&definition)
+    }
+
+    /// Simulates a copy of the component's circuit once per input combination. Sequential
+    /// circuits keep their state from one row to the next.
+    pub fn of_custom_gate(definition: &CustomGate) -> Result<Self, String> {
+        if definition.inputs.len() > MAX_INPUTS {
+            return Err(format!(
+                "{} inputs are more than the {} a truth table supports",
+                definition.inputs.len(),
+                MAX_INPUTS
+            ));
+        }
+
+        let mut circuit = definition.circuit.clone();
+        let count = definition.inputs.len();
+        let mut rows = Vec::with_capacity(1 << count);
+
+        for combination in 0..1u64 << count {
+            let inputs: Vec<bool> = (0..count)
+                .map(|x| combination >> (count - 1 - x) & 1 == 1)
+                .collect();
+            for (id, value) in definition.inputs.iter().zip(inputs.iter()) {
+                if let Some(switch) = circuit.gate_mut(*id) {
+                    switch.set_switch(*value);
+                }
+            }
+
+            simulation::simulate(&mut circuit);
+
+            let outputs = definition
+                .outputs
+                .iter()
+                .map(|id| {
+                    circuit
+                        .gate(*id)
+                        .and_then(|x| x.output_is_on().first().copied())
+                        .unwrap_or(false)
+                })
+                .collect();
+            rows.push((inputs, outputs));
+        }
+
+        Ok(Self {
+            inputs: labels(&circuit, &definition.inputs, "I"),
+            outputs: labels(&circuit, &definition.outputs, "O"),
+            rows,
+        })
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut text = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(|x| csv_field(x))
+            .collect::<Vec<String>>()
+            .join(",");
+        text.push('\n');
+        for (inputs, outputs) in self.rows.iter() {
+            text.push_str(&join_bits(inputs.iter().chain(outputs.iter()), ","));
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let header: Vec<String> = self
+            .inputs
+            .iter()
+            .chain(self.outputs.iter())
+            .map(|x| x.replace('|', "\\|"))
+            .collect();
+        let mut text = format!("| {} |\n", header.join(" | "));
+        text.push_str(&format!("|{}\n", " :-: |".repeat(header.len())));
+        for (inputs, outputs) in self.rows.iter() {
+            text.push_str(&format!(
+                "| {} |\n",
+                join_bits(inputs.iter().chain(outputs.iter()), " | ")
+            ));
+        }
+        text
+    }
+}
+
+/// Gate names where they are set, otherwise `I0`, `I1`, ... or `O0`, `O1`, ...
+fn labels(circuit: &Circuit, ids: &[usize], prefix: &str) -> Vec<String> {
+    ids.iter()
+        .enumerate()
+        .map(|(index, id)| match circuit.gate(*id) {
+            Some(gate) if !gate.gatename.trim().is_empty() => gate.gatename.trim().to_string(),
+            _ => format!("{}{}", prefix, index),
+        })
+        .collect()
+}
+
+fn join_bits<'a>(bits: impl Iterator<Item = &'a bool>, separator: &str) -> String {
+    bits.map(|x| if *x { "1" } else { "0" })
+        .collect::<Vec<&str>>()
+        .join(separator)
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gate::GateType;
+    use crate::testing::{add, wire};
+
+    fn and_circuit() -> Circuit {
+        let mut circuit = Circuit::new();
+        let a = add(&mut circuit, GateType::Switch, 100, 100);
+        let b = add(&mut circuit, GateType::Switch, 100, 200);
+        let and = add(&mut circuit, GateType::And, 250, 150);
+        let out = add(&mut circuit, GateType::Lamp, 400, 150);
+        wire(&mut circuit, a, 0, and, 0);
+        wire(&mut circuit, b, 0, and, 1);
+        wire(&mut circuit, and, 0, out, 0);
+        circuit.gate_mut(a).expect("switch exists").gatename = "A".to_string();
+        circuit.gate_mut(out).expect("lamp exists").gatename = "A,B".to_string();
+        circuit
+    }
+
+    #[test]
+    fn lists_every_combination_with_the_first_input_most_significant() {
+        let table = TruthTable::of_circuit(&and_circuit()).expect("table fits");
+        assert_eq!(table.inputs, ["A", "I1"]);
+        assert_eq!(table.outputs, ["A,B"]);
+        let rows: Vec<(Vec<bool>, Vec<bool>)> = vec![
+            (vec![false, false], vec![false]),
+            (vec![false, true], vec![false]),
+            (vec![true, false], vec![false]),
+            (vec![true, true], vec![true]),
+        ];
+        assert_eq!(table.rows, rows);
+    }
+
+    #[test]
+    fn exports_csv_and_markdown() {
+        let table = TruthTable::of_circuit(&and_circuit()).expect("table fits");
+        assert_eq!(table.to_csv(), "A,I1,\"A,B\"\n0,0,0\n0,1,0\n1,0,0\n1,1,1\n");
+        assert_eq!(
+            table.to_markdown(),
+            "| A | I1 | A,B |\n| :-: | :-: | :-: |\n\
+             | 0 | 0 | 0 |\n| 0 | 1 | 0 |\n| 1 | 0 | 0 |\n| 1 | 1 | 1 |\n"
+        );
+    }
+
+    #[test]
+    fn rejects_more_than_sixteen_inputs() {
+        let mut circuit = Circuit::new();
+        for y in 0..=MAX_INPUTS as i32 {
+            add(&mut circuit, GateType::Switch, 100, 100 + y * 80);
+        }
+        assert!(TruthTable::of_circuit(&circuit).is_err());
+    }
+}